@@ -0,0 +1,134 @@
+//! Layout demands answered by a [`LayoutRunner`] connected to a [`MockServer`], covering the whole
+//! path of a demand: looking up the layout object and the output, `generate_layout_into` with the
+//! reused buffers, and sending the views. Run with `cargo bench --features test-util --bench
+//! demand`.
+//!
+//! Every iteration sends one demand to each output, and the throughput is reported per demand, so
+//! that the cost of looking up the output does not hide behind the number of outputs.
+//!
+//! The allocations of the runner are counted as well. A demand allocates once, for the layout
+//! name sent to the compositor, since the request takes an owned `CString`.
+//!
+//! The timings include the mock compositor, which runs on another thread, so compare them between
+//! revisions, e.g. with `--save-baseline`, rather than reading them as absolute numbers.
+
+use std::alloc::{GlobalAlloc, Layout as AllocLayout, System};
+use std::cell::Cell;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use river_layout_toolkit::mock::MockServer;
+use river_layout_toolkit::{
+    Demand, GeneratedLayout, Layout, LayoutContext, LayoutRunner, Rectangle,
+};
+
+/// Counts the allocations of the runner thread, to report them alongside the timings. The mock
+/// server allocates for every request, so its threads are not counted.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static COUNTED: Cell<bool> = const { Cell::new(false) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: AllocLayout) -> *mut u8 {
+        if COUNTED.try_with(Cell::get).unwrap_or(false) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: AllocLayout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const VIEW_COUNT: u32 = 10;
+
+/// Columns of equal width, written into the buffers of the runner.
+struct Columns;
+
+impl Layout for Columns {
+    type Error = Infallible;
+
+    const NAMESPACE: &'static str = "bench";
+
+    fn generate_layout(&mut self, ctx: &LayoutContext<'_>) -> Result<GeneratedLayout, Infallible> {
+        let views = ctx.usable_area().split_columns(ctx.view_count);
+        Ok(GeneratedLayout::from_views("|||", views))
+    }
+
+    fn generate_layout_into(&mut self, demand: &mut Demand<'_>) -> Result<(), Infallible> {
+        let area = demand.ctx().usable_area();
+        let width = area.width / demand.ctx().view_count.max(1);
+        for i in 0..demand.ctx().view_count {
+            let x = area.x + (i * width) as i32;
+            demand.push_view(Rectangle::new(x, area.y, width, area.height));
+        }
+        demand.set_layout_name("|||");
+        Ok(())
+    }
+}
+
+/// Answer a round of demands, one for each output, with a runner connected to a mock server.
+fn bench_outputs(c: &mut Criterion, outputs: &[&str]) {
+    let (server, conn) = MockServer::start(outputs).unwrap();
+    let mut runner = LayoutRunner::builder()
+        .connection(conn)
+        .build(Columns)
+        .unwrap();
+    let stop = runner.stop_handle().unwrap();
+    let thread = thread::spawn(move || {
+        COUNTED.set(true);
+        runner.run()
+    });
+
+    let round = || {
+        let mut serial = 0;
+        for output in outputs {
+            serial = server
+                .layout_demand(output, VIEW_COUNT, 1920, 1080, 1)
+                .unwrap();
+        }
+        // Demands are answered in order.
+        server.wait_commit(serial).unwrap();
+        server.take_commits();
+    };
+
+    let mut group = c.benchmark_group("demand");
+    group.throughput(Throughput::Elements(outputs.len() as u64));
+    group.bench_function(BenchmarkId::new("outputs", outputs.len()), |b| {
+        b.iter(round);
+    });
+    group.finish();
+
+    const ROUNDS: usize = 1000;
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..ROUNDS {
+        round();
+    }
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    println!(
+        "{} outputs: {} allocations/demand",
+        outputs.len(),
+        allocations as f64 / (ROUNDS * outputs.len()) as f64
+    );
+
+    stop.stop();
+    thread.join().unwrap().unwrap();
+}
+
+fn benches(c: &mut Criterion) {
+    bench_outputs(c, &["A"]);
+    bench_outputs(c, &["A", "B", "C", "D", "E", "F", "G", "H"]);
+}
+
+criterion_group!(demand, benches);
+criterion_main!(demand);
@@ -0,0 +1,66 @@
+//! Throughput of layout generation, without Wayland. Run with
+//! `cargo bench --features testing --bench generate`. See `demand.rs` for demands answered by a
+//! runner.
+
+use std::alloc::{GlobalAlloc, Layout as AllocLayout, System};
+use std::convert::Infallible;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use river_layout_toolkit::layouts::{Grid, MasterStack};
+use river_layout_toolkit::testing::{drive_layout, DemandInput};
+use river_layout_toolkit::{FnLayout, GeneratedLayout, Layout, LayoutContext};
+
+/// Counts allocations, to report them alongside the timings.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: AllocLayout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: AllocLayout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const VIEW_COUNT: u32 = 10;
+
+fn layout(generate: fn(&LayoutContext<'_>) -> GeneratedLayout) -> impl Layout<Error = Infallible> {
+    FnLayout::new("bench", move |ctx| Ok(generate(ctx)))
+}
+
+fn bench_layout(c: &mut Criterion, name: &str, mut layout: impl Layout) {
+    let input = DemandInput::new(VIEW_COUNT, 1920, 1080);
+
+    c.bench_function(name, |b| {
+        b.iter(|| drive_layout(&mut layout, black_box(&input)).unwrap())
+    });
+
+    const DEMANDS: usize = 1000;
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..DEMANDS {
+        black_box(drive_layout(&mut layout, &input).unwrap());
+    }
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    println!("{name}: {} allocations/demand", allocations / DEMANDS);
+}
+
+fn benches(c: &mut Criterion) {
+    bench_layout(
+        c,
+        "master_stack_10",
+        layout(|ctx| MasterStack::default().layout(ctx)),
+    );
+    bench_layout(c, "grid_10", layout(|ctx| Grid::default().layout(ctx)));
+}
+
+criterion_group!(generate, benches);
+criterion_main!(generate);
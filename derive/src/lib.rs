@@ -0,0 +1,113 @@
+//! Derive macro for `river-layout-toolkit`. Use it through the `derive` feature of that crate
+//! instead of depending on this crate directly.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput, LitStr, Path};
+
+/// Implement `Layout` by delegating to functions.
+///
+/// The implementation is configured with a `#[layout(...)]` attribute, which takes the following
+/// comma-separated arguments:
+///
+/// - `namespace = "..."` (required): the value of `Layout::NAMESPACE`.
+/// - `generate = path` (required): the function called from `Layout::generate_layout`, with the
+///   signature `fn(&mut Self, &LayoutContext<'_>) -> Result<GeneratedLayout, Error>`.
+/// - `user_cmd = path`: the function called from `Layout::user_cmd`, with the signature
+///   `fn(&mut Self, String, Option<Tags>, &str) -> Result<UserCmdOutcome, Error>`. Without it,
+///   the default implementation is used, which ignores all commands.
+/// - `error = Type`: the value of `Layout::Error`. Defaults to `std::convert::Infallible`.
+///
+/// Paths are resolved in the scope of the type, so `Self::method` works.
+///
+/// ```ignore
+/// use river_layout_toolkit::{GeneratedLayout, Layout, LayoutContext};
+/// use std::convert::Infallible;
+///
+/// #[derive(Layout)]
+/// #[layout(namespace = "my-layout", generate = Self::generate)]
+/// struct MyLayout;
+///
+/// impl MyLayout {
+///     fn generate(&mut self, ctx: &LayoutContext<'_>) -> Result<GeneratedLayout, Infallible> {
+///         todo!()
+///     }
+/// }
+/// ```
+#[proc_macro_derive(Layout, attributes(layout))]
+pub fn derive_layout(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut namespace: Option<LitStr> = None;
+    let mut generate: Option<Path> = None;
+    let mut user_cmd: Option<Path> = None;
+    let mut error: Option<syn::Type> = None;
+
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("layout")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("namespace") {
+                namespace = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("generate") {
+                generate = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("user_cmd") {
+                user_cmd = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("error") {
+                error = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("unknown `layout` argument"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let missing = |arg: &str| {
+        syn::Error::new_spanned(
+            &input.ident,
+            format!("missing `#[layout({arg} = ...)]` attribute"),
+        )
+    };
+    let namespace = namespace.ok_or_else(|| missing("namespace"))?;
+    let generate = generate.ok_or_else(|| missing("generate"))?;
+    let error = match error {
+        Some(error) => quote!(#error),
+        None => quote!(::std::convert::Infallible),
+    };
+    let user_cmd = user_cmd.map(|user_cmd| {
+        quote! {
+            fn user_cmd(
+                &mut self,
+                cmd: ::std::string::String,
+                tags: ::std::option::Option<::river_layout_toolkit::Tags>,
+                output: &str,
+            ) -> ::std::result::Result<::river_layout_toolkit::UserCmdOutcome, Self::Error> {
+                #user_cmd(self, cmd, tags, output)
+            }
+        }
+    });
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::river_layout_toolkit::Layout for #ident #ty_generics #where_clause {
+            type Error = #error;
+
+            const NAMESPACE: &'static str = #namespace;
+
+            #user_cmd
+
+            fn generate_layout(
+                &mut self,
+                ctx: &::river_layout_toolkit::LayoutContext<'_>,
+            ) -> ::std::result::Result<::river_layout_toolkit::GeneratedLayout, Self::Error> {
+                #generate(self, ctx)
+            }
+        }
+    })
+}
@@ -0,0 +1,45 @@
+use river_layout_toolkit::{GeneratedLayout, Layout, LayoutContext, LayoutRunner, LayoutSource};
+use std::convert::Infallible;
+use std::time::Duration;
+
+use calloop::timer::{TimeoutAction, Timer};
+use calloop::{EventLoop, LoopSignal};
+
+fn main() {
+    let mut event_loop = EventLoop::<LoopSignal>::try_new().unwrap();
+    let handle = event_loop.handle();
+
+    let runner = LayoutRunner::builder().build(MonocleLayout).unwrap();
+    handle
+        .insert_source(LayoutSource::new(runner).unwrap(), |err, _, signal| {
+            eprintln!("layout generator failed: {err}");
+            signal.stop();
+        })
+        .unwrap();
+
+    handle
+        .insert_source(Timer::from_duration(Duration::from_secs(5)), |_, _, _| {
+            println!("still running");
+            TimeoutAction::ToDuration(Duration::from_secs(5))
+        })
+        .unwrap();
+
+    let mut signal = event_loop.get_signal();
+    event_loop.run(None, &mut signal, |_| ()).unwrap();
+}
+
+struct MonocleLayout;
+
+impl Layout for MonocleLayout {
+    type Error = Infallible;
+
+    const NAMESPACE: &'static str = "calloop-layout";
+
+    fn generate_layout(&mut self, ctx: &LayoutContext<'_>) -> Result<GeneratedLayout, Self::Error> {
+        let view = ctx.usable_area();
+        Ok(GeneratedLayout {
+            layout_name: "[M]".to_string(),
+            views: vec![view; ctx.view_count as usize],
+        })
+    }
+}
@@ -0,0 +1,19 @@
+use river_layout_toolkit::layouts::MasterStack;
+use river_layout_toolkit::{run, GeneratedLayout, Layout, LayoutContext};
+use std::convert::Infallible;
+
+fn main() {
+    run(MyLayout::default()).unwrap();
+}
+
+#[derive(Default, Layout)]
+#[layout(namespace = "derive-layout", generate = Self::generate)]
+struct MyLayout {
+    master_stack: MasterStack,
+}
+
+impl MyLayout {
+    fn generate(&mut self, ctx: &LayoutContext<'_>) -> Result<GeneratedLayout, Infallible> {
+        Ok(self.master_stack.layout(ctx))
+    }
+}
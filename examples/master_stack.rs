@@ -0,0 +1,154 @@
+//! A master-stack layout whose main count, main ratio and gaps can be adjusted separately for
+//! every output and tag, e.g.:
+//!
+//! ```sh
+//! riverctl default-layout master-stack
+//! riverctl map normal Super H send-layout-cmd master-stack "main-ratio -0.05"
+//! riverctl map normal Super L send-layout-cmd master-stack "main-ratio +0.05"
+//! riverctl map normal Super+Shift H send-layout-cmd master-stack "main-count +1"
+//! riverctl map normal Super+Shift L send-layout-cmd master-stack "main-count -1"
+//! riverctl map normal Super R send-layout-cmd master-stack "reset-tag"
+//! ```
+//!
+//! With the `persist` feature, the settings survive restarts.
+
+use river_layout_toolkit::command::Command;
+use river_layout_toolkit::math::distribute;
+use river_layout_toolkit::{
+    run, GeneratedLayout, InvalidCommand, Layout, LayoutContext, LayoutState, Rectangle,
+    StateStore, Tags, UserCmdOutcome,
+};
+use std::path::{Path, PathBuf};
+
+fn main() {
+    run(MasterStackLayout::default()).unwrap();
+}
+
+#[derive(Default)]
+struct MasterStackLayout {
+    states: StateStore<LayoutState>,
+    state_path: Option<PathBuf>,
+}
+
+/// The settings are stored for the lowest focused tag, so that viewing several tags at once uses
+/// the settings of one of them.
+fn key(tags: Tags) -> Option<Tags> {
+    tags.primary().map(|index| Tags(1 << index))
+}
+
+/// Split `area` into `count` rows which cover it exactly, the first rows getting the remainder.
+fn rows(area: Rectangle, count: u32) -> impl Iterator<Item = Rectangle> {
+    let mut y = area.y;
+    distribute(area.height, count).map(move |height| {
+        let row = Rectangle::new(area.x, y, area.width, height);
+        y = y.saturating_add_unsigned(height);
+        row
+    })
+}
+
+impl Layout for MasterStackLayout {
+    type Error = InvalidCommand;
+
+    const NAMESPACE: &'static str = "master-stack";
+
+    fn user_cmd(
+        &mut self,
+        cmd: String,
+        tags: Option<Tags>,
+        output: &str,
+    ) -> Result<UserCmdOutcome, Self::Error> {
+        let command = match Command::parse(&cmd) {
+            Ok(Command::Other(_)) | Err(_) => return Err(InvalidCommand(cmd)),
+            Ok(command) => command,
+        };
+        // Commands only come with tags since version 2 of the protocol.
+        let key = tags.and_then(key);
+        // Forgetting the settings makes the next layout demand use the defaults again.
+        match command {
+            Command::Reset => {
+                self.states.remove_output(output);
+                return Ok(UserCmdOutcome::RequestLayout);
+            }
+            Command::ResetTag => {
+                self.states.remove(output, key);
+                return Ok(UserCmdOutcome::RequestLayout);
+            }
+            _ => (),
+        }
+        let state = self
+            .states
+            .get_or_insert_with(output, key, LayoutState::default);
+        match command {
+            Command::MainCount(value) => {
+                let count = value.apply(i64::from(state.main_count));
+                state.main_count = count.clamp(1, 16) as u32;
+            }
+            Command::MainRatio(value) => {
+                state.main_ratio = value.apply(state.main_ratio).clamp(0.1, 0.9);
+            }
+            Command::Gaps(value) => {
+                let gaps = value.apply(i64::from(state.gaps));
+                state.gaps = gaps.clamp(0, 100) as u32;
+            }
+            Command::Reset | Command::ResetTag | Command::Other(_) => unreachable!(),
+        }
+        Ok(UserCmdOutcome::RequestLayout)
+    }
+
+    fn set_state_path(&mut self, path: &Path) {
+        self.state_path = Some(path.to_owned());
+    }
+
+    #[cfg(feature = "persist")]
+    fn on_start(&mut self) -> Result<(), Self::Error> {
+        if let Some(path) = &self.state_path {
+            self.states = StateStore::load(path);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "persist")]
+    fn on_shutdown(&mut self) {
+        if let Some(path) = &self.state_path {
+            if let Err(e) = self.states.save(path) {
+                eprintln!("could not save state: {e}");
+            }
+        }
+    }
+
+    fn output_added(&mut self, output: &str) {
+        eprintln!("output {output} added");
+    }
+
+    fn output_removed(&mut self, output: &str) {
+        // Keep the settings, the output may be connected again later.
+        eprintln!("output {output} removed");
+    }
+
+    fn generate_layout(&mut self, ctx: &LayoutContext<'_>) -> Result<GeneratedLayout, Self::Error> {
+        // Fall back to the settings of the output, which are used with version 1 of the protocol.
+        let state = [key(ctx.tags), None]
+            .into_iter()
+            .find_map(|key| self.states.get(&ctx.output.name, key))
+            .copied()
+            .unwrap_or_default();
+
+        let area = ctx.usable_area();
+        let main_count = state.main_count.min(ctx.view_count);
+        let stack_count = ctx.view_count - main_count;
+
+        let views: Vec<Rectangle> = if stack_count == 0 {
+            rows(area, main_count).collect()
+        } else {
+            let (main, stack) = area.split_horizontal(state.main_ratio);
+            rows(main, main_count)
+                .chain(rows(stack, stack_count))
+                .collect()
+        };
+
+        Ok(GeneratedLayout::from_views(
+            format!("[]= {main_count}"),
+            views.into_iter().map(|view| view.shrink(state.gaps)),
+        ))
+    }
+}
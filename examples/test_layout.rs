@@ -1,4 +1,4 @@
-use river_layout_toolkit::{run, GeneratedLayout, Layout, Rectangle};
+use river_layout_toolkit::{run, GeneratedLayout, Layout, LayoutContext, Rectangle};
 use std::convert::Infallible;
 
 fn main() {
@@ -15,41 +15,25 @@ impl Layout for MyLayout {
 
     const NAMESPACE: &'static str = "test-layout";
 
-    fn user_cmd(
-        &mut self,
-        _cmd: String,
-        _tags: Option<u32>,
-        _output: &str,
-    ) -> Result<(), Self::Error> {
-        Ok(())
-    }
-
-    fn generate_layout(
-        &mut self,
-        view_count: u32,
-        usable_width: u32,
-        usable_height: u32,
-        _tags: u32,
-        _output: &str,
-    ) -> Result<GeneratedLayout, Self::Error> {
+    fn generate_layout(&mut self, ctx: &LayoutContext<'_>) -> Result<GeneratedLayout, Self::Error> {
+        let LayoutContext {
+            view_count,
+            usable_width,
+            usable_height,
+            ..
+        } = *ctx;
         let mut layout = GeneratedLayout {
             layout_name: "[]=".to_string(),
             views: Vec::with_capacity(view_count as usize),
         };
         if view_count == 1 {
-            layout.views.push(Rectangle {
-                x: 0,
-                y: 0,
-                width: usable_width,
-                height: usable_height,
-            });
+            layout
+                .views
+                .push((0, 0, usable_width, usable_height).into());
         } else {
-            layout.views.push(Rectangle {
-                x: 0,
-                y: 0,
-                width: usable_width / 2,
-                height: usable_height,
-            });
+            layout
+                .views
+                .push((0, 0, usable_width / 2, usable_height).into());
             for i in 0..(view_count - 1) {
                 layout.views.push(Rectangle {
                     x: (usable_width / 2) as i32,
@@ -0,0 +1,216 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::path::Path;
+
+use crate::{Demand, GeneratedLayout, Layout, LayoutContext, Tags, UserCmdOutcome, ViewStream};
+
+/// An object-safe version of [`Layout`], with the errors boxed. It is implemented for every
+/// [`Layout`] whose errors are `Send` and `Sync`, and is mostly useful through [`BoxedLayout`].
+///
+/// See [`Layout`] for the documentation of the functions.
+pub trait ErasedLayout: 'static {
+    fn namespace(&self) -> &str;
+    fn user_cmd(
+        &mut self,
+        cmd: String,
+        tags: Option<Tags>,
+        output: &str,
+    ) -> Result<UserCmdOutcome, BoxedError>;
+    fn generate_layout_into(&mut self, demand: &mut Demand<'_>) -> Result<(), BoxedError>;
+    fn stream_layout(&mut self, stream: &mut ViewStream<'_>) -> Option<Result<(), BoxedError>>;
+    fn on_start(&mut self) -> Result<(), BoxedError>;
+    fn set_state_path(&mut self, path: &Path);
+    fn on_shutdown(&mut self);
+    fn output_added(&mut self, output: &str);
+    fn output_removed(&mut self, output: &str);
+    fn usable_area_changed(&mut self, output: &str, old: Option<(u32, u32)>, new: (u32, u32));
+}
+
+impl<L> ErasedLayout for L
+where
+    L: Layout,
+    L::Error: Send + Sync,
+{
+    fn namespace(&self) -> &str {
+        Layout::namespace(self)
+    }
+
+    fn user_cmd(
+        &mut self,
+        cmd: String,
+        tags: Option<Tags>,
+        output: &str,
+    ) -> Result<UserCmdOutcome, BoxedError> {
+        Layout::user_cmd(self, cmd, tags, output).map_err(BoxedError::new)
+    }
+
+    fn generate_layout_into(&mut self, demand: &mut Demand<'_>) -> Result<(), BoxedError> {
+        Layout::generate_layout_into(self, demand).map_err(BoxedError::new)
+    }
+
+    fn stream_layout(&mut self, stream: &mut ViewStream<'_>) -> Option<Result<(), BoxedError>> {
+        Some(Layout::stream_layout(self, stream)?.map_err(BoxedError::new))
+    }
+
+    fn on_start(&mut self) -> Result<(), BoxedError> {
+        Layout::on_start(self).map_err(BoxedError::new)
+    }
+
+    fn set_state_path(&mut self, path: &Path) {
+        Layout::set_state_path(self, path);
+    }
+
+    fn on_shutdown(&mut self) {
+        Layout::on_shutdown(self);
+    }
+
+    fn output_added(&mut self, output: &str) {
+        Layout::output_added(self, output);
+    }
+
+    fn output_removed(&mut self, output: &str) {
+        Layout::output_removed(self, output);
+    }
+
+    fn usable_area_changed(&mut self, output: &str, old: Option<(u32, u32)>, new: (u32, u32)) {
+        Layout::usable_area_changed(self, output, old, new);
+    }
+}
+
+/// A type-erased [`Layout`], for choosing the layout at runtime:
+///
+/// ```no_run
+/// use river_layout_toolkit::layouts::Monocle;
+/// use river_layout_toolkit::{run, BoxedLayout, FnLayout, LayoutSet};
+/// use std::convert::Infallible;
+///
+/// let layout = match std::env::args().nth(1).as_deref() {
+///     Some("monocle") => {
+///         BoxedLayout::new(FnLayout::new("monocle", |ctx| {
+///             Ok::<_, Infallible>(Monocle.layout(ctx))
+///         }))
+///     }
+///     _ => BoxedLayout::new(LayoutSet::new("layouts")),
+/// };
+/// run(layout).unwrap();
+/// ```
+///
+/// Since `BoxedLayout` is a [`Layout`] itself, there is no `From<L: Layout>` conversion. Use
+/// [`BoxedLayout::new`] or [`Layout::boxed`] instead.
+pub struct BoxedLayout {
+    inner: Box<dyn ErasedLayout>,
+}
+
+impl BoxedLayout {
+    pub fn new<L>(layout: L) -> Self
+    where
+        L: Layout,
+        L::Error: Send + Sync,
+    {
+        Self {
+            inner: Box::new(layout),
+        }
+    }
+}
+
+impl From<Box<dyn ErasedLayout>> for BoxedLayout {
+    fn from(inner: Box<dyn ErasedLayout>) -> Self {
+        Self { inner }
+    }
+}
+
+impl Layout for BoxedLayout {
+    type Error = BoxedError;
+
+    /// Unused, the namespace of the wrapped layout is used instead.
+    const NAMESPACE: &'static str = "boxed-layout";
+
+    fn namespace(&self) -> &str {
+        self.inner.namespace()
+    }
+
+    fn user_cmd(
+        &mut self,
+        cmd: String,
+        tags: Option<Tags>,
+        output: &str,
+    ) -> Result<UserCmdOutcome, Self::Error> {
+        self.inner.user_cmd(cmd, tags, output)
+    }
+
+    fn generate_layout(&mut self, ctx: &LayoutContext<'_>) -> Result<GeneratedLayout, Self::Error> {
+        let mut layout = GeneratedLayout::default();
+        let mut demand = Demand::new(*ctx, &mut layout.views, &mut layout.layout_name);
+        self.inner.generate_layout_into(&mut demand)?;
+        Ok(layout)
+    }
+
+    fn generate_layout_into(&mut self, demand: &mut Demand<'_>) -> Result<(), Self::Error> {
+        self.inner.generate_layout_into(demand)
+    }
+
+    fn stream_layout(&mut self, stream: &mut ViewStream<'_>) -> Option<Result<(), Self::Error>> {
+        self.inner.stream_layout(stream)
+    }
+
+    fn on_start(&mut self) -> Result<(), Self::Error> {
+        self.inner.on_start()
+    }
+
+    fn set_state_path(&mut self, path: &Path) {
+        self.inner.set_state_path(path);
+    }
+
+    fn on_shutdown(&mut self) {
+        self.inner.on_shutdown();
+    }
+
+    fn output_added(&mut self, output: &str) {
+        self.inner.output_added(output);
+    }
+
+    fn output_removed(&mut self, output: &str) {
+        self.inner.output_removed(output);
+    }
+
+    fn usable_area_changed(&mut self, output: &str, old: Option<(u32, u32)>, new: (u32, u32)) {
+        self.inner.usable_area_changed(output, old, new);
+    }
+}
+
+/// The error of a [`BoxedLayout`]. It displays and reports the same sources as the error of the
+/// wrapped layout.
+pub struct BoxedError(Box<dyn StdError + Send + Sync>);
+
+impl BoxedError {
+    pub fn new(err: impl StdError + Send + Sync + 'static) -> Self {
+        Self(Box::new(err))
+    }
+
+    /// The error of the wrapped layout, which can be downcast to its concrete type.
+    pub fn inner(&self) -> &(dyn StdError + Send + Sync + 'static) {
+        &*self.0
+    }
+
+    pub fn into_inner(self) -> Box<dyn StdError + Send + Sync> {
+        self.0
+    }
+}
+
+impl fmt::Debug for BoxedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for BoxedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl StdError for BoxedError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.0.source()
+    }
+}
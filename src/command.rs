@@ -0,0 +1,148 @@
+//! Parsing of the commands sent with `riverctl send-layout-cmd`.
+
+use std::str::FromStr;
+
+/// A parsed layout command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// `main-count [+|-]<integer>`
+    MainCount(Value<i64>),
+    /// `main-ratio [+|-]<float>`, where the number is finite.
+    MainRatio(Value<f64>),
+    /// `gaps [+|-]<integer>`
+    Gaps(Value<i64>),
+    /// `reset`, restoring the defaults of the output.
+    Reset,
+    /// `reset-tag`, restoring the defaults of the focused tags of the output.
+    ResetTag,
+    /// Any other command, left untouched.
+    Other(String),
+}
+
+/// The numeric argument of a [`Command`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value<T> {
+    /// A plain number, like `0.6`.
+    Absolute(T),
+    /// A number with an explicit sign, like `+1` or `-0.05`.
+    Relative(T),
+}
+
+impl Value<i64> {
+    /// Compute the new value given the current one.
+    pub fn apply(self, current: i64) -> i64 {
+        match self {
+            Self::Absolute(value) => value,
+            Self::Relative(delta) => current.saturating_add(delta),
+        }
+    }
+}
+
+impl Value<f64> {
+    /// Compute the new value given the current one.
+    pub fn apply(self, current: f64) -> f64 {
+        match self {
+            Self::Absolute(value) => value,
+            Self::Relative(delta) => current + delta,
+        }
+    }
+}
+
+impl<T: FromStr> FromStr for Value<T> {
+    type Err = T::Err;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.parse()?;
+        if s.starts_with(['+', '-']) {
+            Ok(Self::Relative(value))
+        } else {
+            Ok(Self::Absolute(value))
+        }
+    }
+}
+
+/// The error returned by [`Command::parse`] for known commands with a bad argument.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ParseError {
+    #[error("Command '{0}' requires an argument")]
+    MissingArgument(String),
+    #[error("Invalid argument {argument:?} for command '{command}'")]
+    InvalidArgument { command: String, argument: String },
+}
+
+impl Command {
+    /// Parse a command. Unknown commands are returned as [`Command::Other`].
+    pub fn parse(cmd: &str) -> Result<Command, ParseError> {
+        let mut words = cmd.split_whitespace();
+        let Some(name) = words.next() else {
+            return Ok(Self::Other(cmd.to_owned()));
+        };
+        let without_argument = match name {
+            "reset" => Some(Self::Reset),
+            "reset-tag" => Some(Self::ResetTag),
+            _ => None,
+        };
+        if let Some(command) = without_argument {
+            return match words.next() {
+                None => Ok(command),
+                Some(_) => Err(ParseError::InvalidArgument {
+                    command: name.to_owned(),
+                    argument: cmd.trim_start()[name.len()..].trim().to_owned(),
+                }),
+            };
+        }
+        let parse: fn(&str) -> Option<Command> = match name {
+            "main-count" => |arg| arg.parse().ok().map(Self::MainCount),
+            // `f64` also parses `nan` and `inf`, which no ratio can be clamped to.
+            "main-ratio" => |arg| {
+                arg.parse()
+                    .ok()
+                    .filter(|(Value::Absolute(v) | Value::Relative(v)): &Value<f64>| v.is_finite())
+                    .map(Self::MainRatio)
+            },
+            "gaps" => |arg| arg.parse().ok().map(Self::Gaps),
+            _ => return Ok(Self::Other(cmd.to_owned())),
+        };
+        let arg = words
+            .next()
+            .ok_or_else(|| ParseError::MissingArgument(name.to_owned()))?;
+        match parse(arg) {
+            Some(command) if words.next().is_none() => Ok(command),
+            _ => Err(ParseError::InvalidArgument {
+                command: name.to_owned(),
+                argument: cmd.trim_start()[name.len()..].trim().to_owned(),
+            }),
+        }
+    }
+}
+
+impl FromStr for Command {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_finite_ratio() {
+        for arg in ["nan", "-NaN", "inf", "+inf", "-infinity"] {
+            let cmd = format!("main-ratio {arg}");
+            assert_eq!(
+                Command::parse(&cmd),
+                Err(ParseError::InvalidArgument {
+                    command: "main-ratio".to_owned(),
+                    argument: arg.to_owned(),
+                }),
+            );
+        }
+        assert_eq!(
+            Command::parse("main-ratio -0.05"),
+            Ok(Command::MainRatio(Value::Relative(-0.05))),
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+/// How many bytes a client may send without ending the line. Clients which send longer commands
+/// are disconnected, so that a client cannot make the buffers grow without bound.
+const MAX_LINE: usize = 4096;
+
+/// A listening socket for commands from scripts, see
+/// [`RunOptions::control_socket`](crate::RunOptions::control_socket).
+pub(crate) struct ControlSocket {
+    listener: UnixListener,
+    path: PathBuf,
+    clients: Vec<ControlClient>,
+}
+
+impl ControlSocket {
+    pub(crate) fn bind(path: PathBuf) -> io::Result<Self> {
+        let listener = match UnixListener::bind(&path) {
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+                // The file may be left over from an instance which did not shut down cleanly.
+                if UnixStream::connect(&path).is_ok() {
+                    return Err(e);
+                }
+                fs::remove_file(&path)?;
+                UnixListener::bind(&path)?
+            }
+            result => result?,
+        };
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            path,
+            clients: Vec::new(),
+        })
+    }
+
+    /// Accept pending connections and pass the complete commands of all clients to `handle`,
+    /// without blocking. Clients stay connected until they shut down their writing half.
+    pub(crate) fn process(
+        &mut self,
+        mut handle: impl FnMut(&str) -> Result<(), String>,
+    ) -> io::Result<()> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(true)?;
+                    self.clients.push(ControlClient {
+                        stream,
+                        buf: Vec::new(),
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        self.clients
+            .retain_mut(|client| client.process(&mut handle));
+        Ok(())
+    }
+
+    /// The fds of the connected clients, which have to be waited on along with the socket.
+    pub(crate) fn client_fds(&self) -> impl Iterator<Item = RawFd> + '_ {
+        self.clients.iter().map(|client| client.stream.as_raw_fd())
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRawFd for ControlSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// A connection to the control socket, which sends commands, one per line.
+struct ControlClient {
+    stream: UnixStream,
+    /// The start of a command whose line has not ended yet.
+    buf: Vec<u8>,
+}
+
+impl ControlClient {
+    /// Read what the client has sent so far and handle its complete commands, skipping empty lines.
+    /// Returns `false` once the client is done.
+    fn process(&mut self, handle: &mut impl FnMut(&str) -> Result<(), String>) -> bool {
+        let mut chunk = [0; 1024];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    // The last command does not have to end with a newline.
+                    self.buf.push(b'\n');
+                    self.handle_lines(handle);
+                    return false;
+                }
+                Ok(n) => {
+                    self.buf.extend_from_slice(&chunk[..n]);
+                    self.handle_lines(handle);
+                    if self.buf.len() > MAX_LINE {
+                        reply(
+                            &mut self.stream,
+                            Err(format!("commands are limited to {MAX_LINE} bytes")),
+                        );
+                        return false;
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return true,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => {
+                    log::warn!("could not read from the control socket: {e}");
+                    return false;
+                }
+            }
+        }
+    }
+
+    fn handle_lines(&mut self, handle: &mut impl FnMut(&str) -> Result<(), String>) {
+        let mut start = 0;
+        while let Some(len) = self.buf[start..].iter().position(|&b| b == b'\n') {
+            let line = String::from_utf8_lossy(&self.buf[start..start + len]);
+            let cmd = line.trim();
+            if !cmd.is_empty() {
+                reply(&mut self.stream, handle(cmd));
+            }
+            start += len + 1;
+        }
+        self.buf.drain(..start);
+    }
+}
+
+/// Answer a command with `ok` or `error: <message>`. Clients which do not read the answers are
+/// ignored.
+fn reply(stream: &mut UnixStream, result: Result<(), String>) {
+    let line = match result {
+        Ok(()) => "ok\n".to_owned(),
+        Err(e) => format!("error: {e}\n"),
+    };
+    let _ = stream.write_all(line.as_bytes());
+}
@@ -0,0 +1,76 @@
+//! Integration with [`calloop`](::calloop).
+
+use std::io;
+
+use ::calloop::generic::Generic;
+use ::calloop::{EventSource, Interest, Mode, Poll, PostAction, Readiness, Token, TokenFactory};
+use wayrs_client::IoMode;
+
+use crate::{Error, Layout, LayoutRunner};
+
+/// A calloop event source which drives a [`LayoutRunner`].
+///
+/// The callback is only invoked if the layout generator fails, at which point the source removes
+/// itself from the event loop.
+pub struct LayoutSource<L: Layout> {
+    generic: Generic<LayoutRunner<L>>,
+}
+
+impl<L: Layout> LayoutSource<L> {
+    /// Wrap a runner. This handles the already queued events right away.
+    pub fn new(mut runner: LayoutRunner<L>) -> Result<Self, Error<L::Error>> {
+        runner.step(IoMode::NonBlocking)?;
+        Ok(Self {
+            generic: Generic::new(runner, Interest::READ, Mode::Level),
+        })
+    }
+}
+
+impl<L: Layout> EventSource for LayoutSource<L> {
+    type Event = Error<L::Error>;
+    type Metadata = ();
+    type Ret = ();
+    type Error = io::Error;
+
+    fn process_events<F>(
+        &mut self,
+        readiness: Readiness,
+        token: Token,
+        mut callback: F,
+    ) -> Result<PostAction, Self::Error>
+    where
+        F: FnMut(Self::Event, &mut Self::Metadata) -> Self::Ret,
+    {
+        self.generic.process_events(readiness, token, |_, runner| {
+            // SAFETY: the runner is not moved out, so its socket is not dropped.
+            let runner = unsafe { runner.get_mut() };
+            match runner.step(IoMode::NonBlocking) {
+                Ok(()) => Ok(PostAction::Continue),
+                Err(err) => {
+                    callback(err, &mut ());
+                    Ok(PostAction::Remove)
+                }
+            }
+        })
+    }
+
+    fn register(
+        &mut self,
+        poll: &mut Poll,
+        token_factory: &mut TokenFactory,
+    ) -> ::calloop::Result<()> {
+        self.generic.register(poll, token_factory)
+    }
+
+    fn reregister(
+        &mut self,
+        poll: &mut Poll,
+        token_factory: &mut TokenFactory,
+    ) -> ::calloop::Result<()> {
+        self.generic.reregister(poll, token_factory)
+    }
+
+    fn unregister(&mut self, poll: &mut Poll) -> ::calloop::Result<()> {
+        self.generic.unregister(poll)
+    }
+}
@@ -0,0 +1,289 @@
+//! Layouts computed by another program, available with the `external` feature.
+//!
+//! Demands are serialized as [`LayoutRequest`]s and answered with [`LayoutResponse`]s, both as
+//! JSON objects:
+//!
+//! ```json
+//! {"view_count":2,"usable_width":1920,"usable_height":1080,"tags":1,"output":"eDP-1","serial":7}
+//! {"layout_name":"[]=","views":[{"x":0,"y":0,"width":960,"height":1080},{"x":960,"y":0,"width":960,"height":1080}]}
+//! ```
+//!
+//! [`ExternalLayout`] runs a program which reads one request per line from its stdin and writes
+//! one response per line to its stdout. The helpers are public for exchanging the messages in
+//! other ways.
+
+use std::ffi::{OsStr, OsString};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::fd::AsRawFd;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::poll::poll_readable;
+use crate::{GeneratedLayout, Layout, LayoutContext, Rectangle, Tags, ValidationError};
+
+/// A layout demand, see [`LayoutContext`] for the meaning of the fields.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct LayoutRequest {
+    pub view_count: u32,
+    pub usable_width: u32,
+    pub usable_height: u32,
+    pub tags: Tags,
+    pub output: String,
+    pub serial: u32,
+}
+
+impl LayoutRequest {
+    pub fn new(ctx: &LayoutContext<'_>) -> Self {
+        Self {
+            view_count: ctx.view_count,
+            usable_width: ctx.usable_width,
+            usable_height: ctx.usable_height,
+            tags: ctx.tags,
+            output: ctx.output.name.clone(),
+            serial: ctx.serial,
+        }
+    }
+
+    /// The request as a single line of JSON, without the trailing newline.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("requests are always serializable")
+    }
+}
+
+/// The answer to a [`LayoutRequest`], see [`GeneratedLayout`].
+#[derive(Debug, Clone, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub struct LayoutResponse {
+    #[serde(default)]
+    pub layout_name: String,
+    pub views: Vec<Rectangle>,
+}
+
+impl LayoutResponse {
+    /// Parse a response and check that it has `view_count` views.
+    pub fn parse(json: &str, view_count: u32) -> Result<GeneratedLayout, ExternalError> {
+        let response: Self = serde_json::from_str(json)?;
+        Ok(response.into_layout(view_count)?)
+    }
+
+    /// Check that the response has `view_count` views.
+    pub fn into_layout(self, view_count: u32) -> Result<GeneratedLayout, ValidationError> {
+        let layout = GeneratedLayout::from_views(self.layout_name, self.views);
+        if layout.has_view_count(view_count) {
+            Ok(layout)
+        } else {
+            Err(ValidationError::ViewCount {
+                expected: view_count,
+                got: layout.views.len(),
+            })
+        }
+    }
+}
+
+/// The error of an [`ExternalLayout`].
+#[derive(Debug, thiserror::Error)]
+pub enum ExternalError {
+    #[error("could not talk to the layout program: {0}")]
+    Io(#[from] io::Error),
+    #[error("the layout program exited")]
+    Exited,
+    #[error("the layout program did not respond within {0:?}")]
+    Timeout(Duration),
+    #[error("invalid response: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid layout: {0}")]
+    Invalid(#[from] ValidationError),
+}
+
+/// A layout computed by a program which speaks JSON over stdin and stdout, see the
+/// [module documentation](self).
+///
+/// The program is started on the first demand and kept running. If it exits, the demand fails
+/// with [`ExternalError::Exited`] and the program is started again on the next one. If it does not
+/// respond within the [timeout](Self::timeout), the demand fails with [`ExternalError::Timeout`]
+/// and the program is killed, so that a late response is not mistaken for the next one. Commands
+/// are ignored.
+///
+/// ```no_run
+/// use river_layout_toolkit::external::ExternalLayout;
+/// use river_layout_toolkit::run;
+///
+/// run(ExternalLayout::new("py-layout", "python3").arg("layout.py")).unwrap();
+/// ```
+pub struct ExternalLayout {
+    namespace: String,
+    program: OsString,
+    args: Vec<OsString>,
+    timeout: Duration,
+    process: Option<Process>,
+}
+
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl ExternalLayout {
+    /// How long the program has to respond by default.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+    pub fn new(namespace: impl Into<String>, program: impl AsRef<OsStr>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            program: program.as_ref().to_owned(),
+            args: Vec::new(),
+            timeout: Self::DEFAULT_TIMEOUT,
+            process: None,
+        }
+    }
+
+    /// Pass an argument to the program.
+    #[must_use]
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    /// How long the program has to respond to a request, [`DEFAULT_TIMEOUT`](Self::DEFAULT_TIMEOUT)
+    /// by default.
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn process(&mut self) -> io::Result<&mut Process> {
+        if self.process.is_none() {
+            let mut child = Command::new(&self.program)
+                .args(&self.args)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()?;
+            let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+                unreachable!("both are piped");
+            };
+            self.process = Some(Process {
+                child,
+                stdin,
+                stdout: BufReader::new(stdout),
+            });
+        }
+        Ok(self.process.as_mut().unwrap())
+    }
+
+    fn request(&mut self, request: &LayoutRequest) -> Result<String, ExternalError> {
+        let timeout = self.timeout;
+        let process = self.process()?;
+        let mut line = request.to_json();
+        line.push('\n');
+        let result = process
+            .stdin
+            .write_all(line.as_bytes())
+            .and_then(|()| process.stdin.flush())
+            .and_then(|()| process.read_line(timeout));
+        match result {
+            Ok(Some(response)) => Ok(response),
+            Ok(None) => {
+                self.kill();
+                Err(ExternalError::Timeout(timeout))
+            }
+            // A closed pipe means that the program is gone.
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Err(self.restart()),
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Err(self.restart()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Forget the exited process, so that the next demand starts a new one.
+    fn restart(&mut self) -> ExternalError {
+        if let Some(mut process) = self.process.take() {
+            let _ = process.child.wait();
+        }
+        ExternalError::Exited
+    }
+
+    fn kill(&mut self) {
+        if let Some(mut process) = self.process.take() {
+            let _ = process.child.kill();
+            let _ = process.child.wait();
+        }
+    }
+}
+
+impl Process {
+    /// Read one line from stdout, or `None` if it is not complete within `timeout`.
+    fn read_line(&mut self, timeout: Duration) -> io::Result<Option<String>> {
+        let deadline = Instant::now() + timeout;
+        let mut line = Vec::new();
+        loop {
+            // Only read when the buffer is empty and the pipe is readable, so that a read never
+            // blocks past the deadline.
+            let buf = self.stdout.buffer();
+            if let Some(end) = buf.iter().position(|&b| b == b'\n') {
+                line.extend_from_slice(&buf[..=end]);
+                self.stdout.consume(end + 1);
+                return String::from_utf8(line)
+                    .map(Some)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+            }
+            line.extend_from_slice(buf);
+            let len = buf.len();
+            self.stdout.consume(len);
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let fd = self.stdout.get_ref().as_raw_fd();
+            if remaining.is_zero() || !poll_readable([fd], Some(remaining))?[0] {
+                return Ok(None);
+            }
+            if self.stdout.fill_buf()?.is_empty() {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+}
+
+impl Layout for ExternalLayout {
+    type Error = ExternalError;
+
+    /// Unused, the namespace passed to [`ExternalLayout::new`] is used instead.
+    const NAMESPACE: &'static str = "external";
+
+    fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    fn generate_layout(&mut self, ctx: &LayoutContext<'_>) -> Result<GeneratedLayout, Self::Error> {
+        let response = self.request(&LayoutRequest::new(ctx))?;
+        LayoutResponse::parse(&response, ctx.view_count)
+    }
+}
+
+impl Drop for ExternalLayout {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{drive_layout, DemandInput};
+    use crate::Error;
+
+    #[test]
+    fn timeout() {
+        let timeout = Duration::from_millis(100);
+        let mut layout = ExternalLayout::new("external", "sleep")
+            .arg("10")
+            .timeout(timeout);
+        let start = Instant::now();
+        let err = drive_layout(&mut layout, &DemandInput::new(1, 100, 100)).unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(
+            matches!(err, Error::LayoutError(ExternalError::Timeout(t)) if t == timeout),
+            "{err}"
+        );
+        // The unresponsive program is killed and started again on the next demand.
+        assert!(layout.process.is_none());
+    }
+}
@@ -0,0 +1,72 @@
+use std::error::Error as StdError;
+
+use crate::{GeneratedLayout, Layout, LayoutContext, Tags, UserCmdOutcome};
+
+type UserCmdFn<E> = Box<dyn FnMut(String, Option<Tags>, &str) -> Result<UserCmdOutcome, E>>;
+
+/// A [`Layout`] made of closures, for quick prototypes. See also [`run_fn`](crate::run_fn).
+pub struct FnLayout<G, E> {
+    namespace: String,
+    generate: G,
+    user_cmd: Option<UserCmdFn<E>>,
+}
+
+impl<G, E> FnLayout<G, E>
+where
+    G: FnMut(&LayoutContext<'_>) -> Result<GeneratedLayout, E> + 'static,
+    E: StdError + 'static,
+{
+    /// Create a layout which ignores all commands.
+    pub fn new(namespace: impl Into<String>, generate: G) -> Self {
+        Self {
+            namespace: namespace.into(),
+            generate,
+            user_cmd: None,
+        }
+    }
+
+    /// Handle commands with the given closure, which takes the same arguments as
+    /// [`Layout::user_cmd`].
+    #[must_use]
+    pub fn user_cmd(
+        mut self,
+        user_cmd: impl FnMut(String, Option<Tags>, &str) -> Result<UserCmdOutcome, E> + 'static,
+    ) -> Self {
+        self.user_cmd = Some(Box::new(user_cmd));
+        self
+    }
+}
+
+impl<G, E> Layout for FnLayout<G, E>
+where
+    G: FnMut(&LayoutContext<'_>) -> Result<GeneratedLayout, E> + 'static,
+    E: StdError + 'static,
+{
+    type Error = E;
+
+    /// Unused, the namespace passed to [`FnLayout::new`] is used instead.
+    const NAMESPACE: &'static str = "fn-layout";
+
+    fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    fn user_cmd(
+        &mut self,
+        cmd: String,
+        tags: Option<Tags>,
+        output: &str,
+    ) -> Result<UserCmdOutcome, Self::Error> {
+        match &mut self.user_cmd {
+            Some(user_cmd) => user_cmd(cmd, tags, output),
+            None => {
+                log::debug!("ignoring command {cmd:?}");
+                Ok(UserCmdOutcome::Handled)
+            }
+        }
+    }
+
+    fn generate_layout(&mut self, ctx: &LayoutContext<'_>) -> Result<GeneratedLayout, Self::Error> {
+        (self.generate)(ctx)
+    }
+}
@@ -0,0 +1,296 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    GeneratedLayout, InvalidCommand, Layout, LayoutContext, StateStore, Tags, UserCmdOutcome,
+};
+
+type GenerateFn = Box<dyn FnMut(&LayoutContext<'_>) -> GeneratedLayout>;
+
+/// Several named layouts, one of which is active on each output.
+///
+/// The active layout is switched with the following commands:
+///
+/// - `next-layout` and `prev-layout` cycle through the layouts in the order they were added.
+/// - `set-layout <name>` selects a layout by name.
+///
+/// Layouts are committed under the name they were added with, replacing the name they generated.
+/// Outputs start with the first layout. With the `persist` feature, the selection is loaded on
+/// start and saved when the layout generator stops, see [`Layout::set_state_path`] and
+/// [`Layout::on_shutdown`].
+///
+/// By default, the selection applies to the whole output. See [`per_tag`](Self::per_tag) for
+/// selecting a layout for each tag instead.
+///
+/// ```no_run
+/// use river_layout_toolkit::layouts::{Grid, MasterStack, Monocle};
+/// use river_layout_toolkit::{run, LayoutSet};
+///
+/// let layouts = LayoutSet::new("my-layouts")
+///     .with_layout("[]=", |ctx| MasterStack::default().layout(ctx))
+///     .with_layout("[M]", |ctx| Monocle.layout(ctx))
+///     .with_layout("[#]", |ctx| Grid::default().layout(ctx));
+/// run(layouts).unwrap();
+/// ```
+pub struct LayoutSet {
+    namespace: String,
+    layouts: Vec<(String, GenerateFn)>,
+    selected: StateStore<String>,
+    per_tag: bool,
+    state_path: Option<PathBuf>,
+}
+
+impl LayoutSet {
+    /// Create an empty set. At least one layout has to be added with
+    /// [`with_layout`](Self::with_layout), otherwise no views are generated.
+    pub fn new(namespace: impl Into<String>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            layouts: Vec::new(),
+            selected: StateStore::new(),
+            per_tag: false,
+            state_path: None,
+        }
+    }
+
+    /// Add a layout. Names should be unique, `set-layout` selects the first layout with the
+    /// given name.
+    #[must_use]
+    pub fn with_layout(
+        mut self,
+        name: impl Into<String>,
+        generate: impl FnMut(&LayoutContext<'_>) -> GeneratedLayout + 'static,
+    ) -> Self {
+        self.layouts.push((name.into(), Box::new(generate)));
+        self
+    }
+
+    /// Select the layout for each set of focused tags instead of for the whole output.
+    ///
+    /// The commands change the selection for the tags which were focused when the command was
+    /// sent, and `set-layout-for-tag <n> <name>` selects a layout for the single tag `<n>`,
+    /// counting from `1` like status bars do. When a layout is demanded with several tags focused,
+    /// the selection for exactly these tags is used if there is one, otherwise the one of the
+    /// lowest focused tag. Tags without a selection fall back to the selection of the output, then
+    /// to the first layout.
+    ///
+    /// With version 1 of the protocol, commands do not come with their tags, so they always change
+    /// the selection of the output, see [`LayoutRunner::protocol_version`].
+    ///
+    /// [`LayoutRunner::protocol_version`]: crate::LayoutRunner::protocol_version
+    #[must_use]
+    pub fn per_tag(mut self) -> Self {
+        self.per_tag = true;
+        self
+    }
+
+    /// The names of all layouts, in the order they were added.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.layouts.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// The name of the layout active on the given output with the given focused tags, if there are
+    /// any layouts. The tags are ignored unless [`per_tag`](Self::per_tag) is set.
+    pub fn current(&self, output: &str, tags: Tags) -> Option<&str> {
+        self.layouts
+            .get(self.current_index(output, Some(tags)))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// The index of the active layout. Selections which do not match any layout, e.g. loaded from
+    /// an outdated state file, are skipped.
+    fn current_index(&self, output: &str, tags: Option<Tags>) -> usize {
+        let tags = tags.filter(|_| self.per_tag);
+        let exact = tags.filter(|tags| !tags.is_empty());
+        let lowest = tags.and_then(lowest_tag);
+        [exact, lowest, None]
+            .into_iter()
+            .filter_map(|key| self.selected.get(output, key))
+            .find_map(|name| self.position(name))
+            .unwrap_or(0)
+    }
+
+    /// The key under which commands sent with the given tags store the selection.
+    fn selection_key(&self, tags: Option<Tags>) -> Option<Tags> {
+        tags.filter(|tags| self.per_tag && !tags.is_empty())
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.layouts.iter().position(|(n, _)| n == name)
+    }
+
+    fn select(&mut self, output: &str, key: Option<Tags>, index: usize) {
+        let name = self.layouts[index].0.clone();
+        self.selected.insert(output, key, name);
+    }
+}
+
+/// The single lowest tag of `tags`.
+fn lowest_tag(tags: Tags) -> Option<Tags> {
+    tags.primary().map(|index| Tags(1 << index))
+}
+
+impl Layout for LayoutSet {
+    type Error = InvalidCommand;
+
+    /// Unused, the namespace passed to [`LayoutSet::new`] is used instead.
+    const NAMESPACE: &'static str = "layout-set";
+
+    fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    fn user_cmd(
+        &mut self,
+        cmd: String,
+        tags: Option<Tags>,
+        output: &str,
+    ) -> Result<UserCmdOutcome, Self::Error> {
+        let len = self.layouts.len();
+        let current = self.current_index(output, tags);
+        let mut key = self.selection_key(tags);
+        let index = match cmd.split_whitespace().collect::<Vec<_>>()[..] {
+            ["next-layout"] if len > 0 => (current + 1) % len,
+            ["prev-layout"] if len > 0 => (current + len - 1) % len,
+            ["set-layout", name] => match self.position(name) {
+                Some(index) => index,
+                None => return Err(InvalidCommand(cmd)),
+            },
+            ["set-layout-for-tag", tag, name] if self.per_tag => {
+                let tag = tag.parse::<u32>().ok().filter(|tag| (1..=32).contains(tag));
+                match (tag, self.position(name)) {
+                    (Some(tag), Some(index)) => {
+                        key = Some(Tags(1 << (tag - 1)));
+                        index
+                    }
+                    _ => return Err(InvalidCommand(cmd)),
+                }
+            }
+            _ => return Err(InvalidCommand(cmd)),
+        };
+        self.select(output, key, index);
+        Ok(UserCmdOutcome::RequestLayout)
+    }
+
+    fn set_state_path(&mut self, path: &Path) {
+        self.state_path = Some(path.to_owned());
+    }
+
+    #[cfg(feature = "persist")]
+    fn on_start(&mut self) -> Result<(), Self::Error> {
+        if let Some(path) = &self.state_path {
+            self.selected = StateStore::load(path);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "persist")]
+    fn on_shutdown(&mut self) {
+        if let Some(path) = &self.state_path {
+            if let Err(e) = self.selected.save(path) {
+                log::warn!("could not save state to {}: {e}", path.display());
+            }
+        }
+    }
+
+    fn generate_layout(&mut self, ctx: &LayoutContext<'_>) -> Result<GeneratedLayout, Self::Error> {
+        let index = self.current_index(&ctx.output.name, Some(ctx.tags));
+        let Some((name, generate)) = self.layouts.get_mut(index) else {
+            log::warn!("no layouts were added to the set");
+            return Ok(GeneratedLayout::default());
+        };
+        let mut layout = generate(ctx);
+        layout.layout_name.clone_from(name);
+        Ok(layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{drive_layout, DemandInput};
+
+    fn layouts() -> LayoutSet {
+        LayoutSet::new("test")
+            .with_layout("a", |_| GeneratedLayout::default())
+            .with_layout("b", |_| GeneratedLayout::default())
+            .with_layout("c", |_| GeneratedLayout::default())
+    }
+
+    fn cmd(layouts: &mut LayoutSet, cmd: &str, tags: Option<Tags>) -> Result<(), InvalidCommand> {
+        let outcome = layouts.user_cmd(cmd.to_owned(), tags, "eDP-1")?;
+        assert_eq!(outcome, UserCmdOutcome::RequestLayout);
+        Ok(())
+    }
+
+    #[test]
+    fn cycle() {
+        let mut layouts = layouts();
+        assert_eq!(layouts.current("eDP-1", Tags(1)), Some("a"));
+        cmd(&mut layouts, "prev-layout", None).unwrap();
+        assert_eq!(layouts.current("eDP-1", Tags(1)), Some("c"));
+        cmd(&mut layouts, "next-layout", None).unwrap();
+        assert_eq!(layouts.current("eDP-1", Tags(1)), Some("a"));
+        cmd(&mut layouts, "set-layout c", None).unwrap();
+        cmd(&mut layouts, "next-layout", None).unwrap();
+        assert_eq!(layouts.current("eDP-1", Tags(1)), Some("a"));
+        // Other outputs keep their own selection.
+        assert_eq!(layouts.current("HDMI-A-1", Tags(1)), Some("a"));
+
+        let layout = drive_layout(&mut layouts, &DemandInput::new(0, 100, 100)).unwrap();
+        assert_eq!(layout.layout_name, "a");
+    }
+
+    #[test]
+    fn invalid_commands() {
+        let mut layouts = layouts();
+        cmd(&mut layouts, "set-layout b", None).unwrap();
+        for invalid in [
+            "set-layout d",
+            "set-layout",
+            "set-layout-for-tag 1 a",
+            "bogus",
+        ] {
+            let err = cmd(&mut layouts, invalid, None).unwrap_err();
+            assert_eq!(err.0, invalid);
+        }
+        assert_eq!(layouts.current("eDP-1", Tags(1)), Some("b"));
+    }
+
+    #[test]
+    fn per_tag() {
+        let mut layouts = layouts().per_tag();
+        cmd(&mut layouts, "set-layout b", None).unwrap();
+        cmd(&mut layouts, "set-layout c", Some(Tags(0b10))).unwrap();
+        cmd(&mut layouts, "set-layout a", Some(Tags(0b110))).unwrap();
+        assert_eq!(layouts.current("eDP-1", Tags(0b10)), Some("c"));
+        // An exact selection wins over the one of the lowest tag.
+        assert_eq!(layouts.current("eDP-1", Tags(0b110)), Some("a"));
+        assert_eq!(layouts.current("eDP-1", Tags(0b1010)), Some("c"));
+        // Tags without a selection use the one of the output.
+        assert_eq!(layouts.current("eDP-1", Tags(0b1)), Some("b"));
+        assert_eq!(layouts.current("eDP-1", Tags(0b101)), Some("b"));
+        // `next-layout` only changes the selection of the focused tags.
+        cmd(&mut layouts, "next-layout", Some(Tags(0b10))).unwrap();
+        assert_eq!(layouts.current("eDP-1", Tags(0b10)), Some("a"));
+        assert_eq!(layouts.current("eDP-1", Tags(0b1)), Some("b"));
+    }
+
+    #[test]
+    fn set_layout_for_tag() {
+        let mut layouts = layouts().per_tag();
+        cmd(&mut layouts, "set-layout-for-tag 3 c", Some(Tags(0b1))).unwrap();
+        assert_eq!(layouts.current("eDP-1", Tags(0b100)), Some("c"));
+        // Neither the focused tags nor other tags are affected.
+        for tags in [0b1, 0b10, 0b1000] {
+            assert_eq!(layouts.current("eDP-1", Tags(tags)), Some("a"));
+        }
+        for invalid in [
+            "set-layout-for-tag 0 a",
+            "set-layout-for-tag 33 a",
+            "set-layout-for-tag 1 d",
+        ] {
+            let err = cmd(&mut layouts, invalid, None).unwrap_err();
+            assert_eq!(err.0, invalid);
+        }
+    }
+}
@@ -0,0 +1,537 @@
+//! Ready-made layouts, which can be used as building blocks or as reference implementations.
+//!
+//! All layouts cover the usable area exactly. When the space cannot be divided evenly among views,
+//! e.g. into rows of a stack, the leftover pixels are given to the first views. When it is split by
+//! a ratio, e.g. into the main and the stack area, the left or top part gets its share rounded
+//! down, so the leftover pixels go to the right or bottom part.
+
+use crate::math::{checked_split, grid_size, scale};
+use crate::{GeneratedLayout, LayoutContext, Rectangle};
+
+/// Where the main area is placed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    #[default]
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Orientation {
+    /// Split `area` into the main and the stack areas, the main area getting `ratio` of the space.
+    /// Together they cover `area` exactly, the leftover pixel going to the right or bottom area.
+    pub fn split(self, area: Rectangle, ratio: f64) -> (Rectangle, Rectangle) {
+        match self {
+            Self::Left => area.split_horizontal(ratio),
+            Self::Right => swap(area.split_horizontal(1.0 - ratio)),
+            Self::Top => area.split_vertical(ratio),
+            Self::Bottom => swap(area.split_vertical(1.0 - ratio)),
+        }
+    }
+
+    /// The next orientation clockwise: left, top, right, bottom.
+    #[must_use]
+    pub fn rotate_cw(self) -> Self {
+        match self {
+            Self::Left => Self::Top,
+            Self::Top => Self::Right,
+            Self::Right => Self::Bottom,
+            Self::Bottom => Self::Left,
+        }
+    }
+
+    /// The next orientation counter-clockwise: left, bottom, right, top.
+    #[must_use]
+    pub fn rotate_ccw(self) -> Self {
+        match self {
+            Self::Left => Self::Bottom,
+            Self::Bottom => Self::Right,
+            Self::Right => Self::Top,
+            Self::Top => Self::Left,
+        }
+    }
+}
+
+/// The classic layout: `main_count` views in the main area, the rest in a stack next to it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MasterStack {
+    /// The number of views in the main area.
+    pub main_count: u32,
+    /// The fraction of the usable area taken by the main area, between `0` and `1`.
+    pub main_ratio: f64,
+    pub orientation: Orientation,
+}
+
+impl Default for MasterStack {
+    fn default() -> Self {
+        Self {
+            main_count: 1,
+            main_ratio: 0.6,
+            orientation: Orientation::Left,
+        }
+    }
+}
+
+impl MasterStack {
+    pub fn layout(&self, ctx: &LayoutContext<'_>) -> GeneratedLayout {
+        let layout_name = match self.orientation {
+            Orientation::Left => "[]=",
+            Orientation::Right => "=[]",
+            Orientation::Top => "[^]",
+            Orientation::Bottom => "[_]",
+        };
+        let mut views = Vec::with_capacity(ctx.view_count as usize);
+
+        let area = ctx.usable_area();
+        let main_count = self.main_count.min(ctx.view_count);
+        let stack_count = ctx.view_count - main_count;
+        let horizontal = matches!(self.orientation, Orientation::Left | Orientation::Right);
+
+        if main_count == 0 || stack_count == 0 {
+            views.extend(tile(area, ctx.view_count, !horizontal));
+        } else {
+            let (main, stack) = self.orientation.split(area, self.main_ratio);
+            views.extend(tile(main, main_count, !horizontal));
+            views.extend(tile(stack, stack_count, !horizontal));
+        }
+
+        GeneratedLayout {
+            layout_name: layout_name.into(),
+            views,
+        }
+    }
+}
+
+/// One main view in the middle, with the other views split between stacks on both sides. The
+/// right stack gets the first views and, with an odd number of them, one view more.
+///
+/// With two views, there is only one stack, and the layout is the same as [`MasterStack`] with
+/// the main area on the left.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CenteredMaster {
+    /// The fraction of the usable width taken by the main view, between `0` and `1`.
+    pub main_ratio: f64,
+    /// The fraction of the remaining width taken by the left stack, between `0` and `1`.
+    pub side_ratio: f64,
+}
+
+impl Default for CenteredMaster {
+    fn default() -> Self {
+        Self {
+            main_ratio: 0.5,
+            side_ratio: 0.5,
+        }
+    }
+}
+
+impl CenteredMaster {
+    pub fn layout(&self, ctx: &LayoutContext<'_>) -> GeneratedLayout {
+        let area = ctx.usable_area();
+        let mut views = Vec::with_capacity(ctx.view_count as usize);
+
+        match ctx.view_count {
+            0 => (),
+            1 => views.push(area),
+            2 => {
+                let (main, stack) = area.split_horizontal(self.main_ratio);
+                views.extend([main, stack]);
+            }
+            view_count => {
+                let main_width = scale(area.width, self.main_ratio.clamp(0.0, 1.0));
+                let side_width = area.width - main_width;
+                let left_width = scale(side_width, self.side_ratio.clamp(0.0, 1.0));
+                let left = Rectangle::new(area.x, area.y, left_width, area.height);
+                let main = Rectangle::new(
+                    area.x.saturating_add_unsigned(left_width),
+                    area.y,
+                    main_width,
+                    area.height,
+                );
+                let right = Rectangle::new(
+                    main.x.saturating_add_unsigned(main_width),
+                    area.y,
+                    side_width - left_width,
+                    area.height,
+                );
+                let stack_count = view_count - 1;
+                views.push(main);
+                views.extend(tile(right, stack_count.div_ceil(2), false));
+                views.extend(tile(left, stack_count / 2, false));
+            }
+        }
+
+        GeneratedLayout {
+            layout_name: "|M|".into(),
+            views,
+        }
+    }
+}
+
+/// Views arranged in a grid of roughly equal cells, see [`grid_size`]. If the last row is not
+/// full, its views are wider.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Grid {
+    pub fill_order: FillOrder,
+    pub orientation: GridOrientation,
+}
+
+/// The order in which the views of a [`Grid`] fill its cells.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FillOrder {
+    /// Left to right, then top to bottom.
+    #[default]
+    RowMajor,
+    /// Top to bottom, then left to right.
+    ColumnMajor,
+}
+
+/// Whether a [`Grid`] is made of rows or of columns.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GridOrientation {
+    /// Full rows and an incomplete last row with wider views. There are at least as many columns
+    /// as rows, which suits landscape outputs.
+    #[default]
+    Rows,
+    /// Full columns and an incomplete last column with taller views. There are at least as many
+    /// rows as columns, which suits portrait outputs.
+    Columns,
+}
+
+impl Grid {
+    pub fn layout(&self, ctx: &LayoutContext<'_>) -> GeneratedLayout {
+        let area = ctx.usable_area();
+        let count = ctx.view_count;
+        let rows = self.orientation == GridOrientation::Rows;
+        // The grid is made of `lines`, which are rows or columns depending on the orientation.
+        let (per_line, lines) = grid_size(count);
+        let (line_total, cell_total) = if rows {
+            (area.height, area.width)
+        } else {
+            (area.width, area.height)
+        };
+        let in_line = move |line: u32| per_line.min(count - line * per_line);
+        let cell = move |(line, index): (u32, u32)| {
+            let (line_offset, line_len) = checked_split(line_total, lines, line)?;
+            let (offset, len) = checked_split(cell_total, in_line(line), index)?;
+            Some(if rows {
+                Rectangle::new(
+                    area.x.saturating_add_unsigned(offset),
+                    area.y.saturating_add_unsigned(line_offset),
+                    len,
+                    line_len,
+                )
+            } else {
+                Rectangle::new(
+                    area.x.saturating_add_unsigned(line_offset),
+                    area.y.saturating_add_unsigned(offset),
+                    line_len,
+                    len,
+                )
+            })
+        };
+
+        let along_lines = (self.fill_order == FillOrder::RowMajor) == rows;
+        let views = if along_lines {
+            (0..lines)
+                .flat_map(|line| (0..in_line(line)).map(move |index| (line, index)))
+                .filter_map(cell)
+                .collect()
+        } else {
+            (0..per_line)
+                .flat_map(|index| {
+                    (0..lines)
+                        .filter(move |&line| index < in_line(line))
+                        .map(move |line| (line, index))
+                })
+                .filter_map(cell)
+                .collect()
+        };
+
+        GeneratedLayout {
+            layout_name: "[#]".into(),
+            views,
+        }
+    }
+}
+
+/// Every view takes the whole usable area.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Monocle;
+
+impl Monocle {
+    pub fn layout(&self, ctx: &LayoutContext<'_>) -> GeneratedLayout {
+        let area = ctx.usable_area();
+        GeneratedLayout {
+            layout_name: "[M]".into(),
+            views: vec![area; ctx.view_count as usize],
+        }
+    }
+}
+
+/// A part of the usable area, which can be bisected recursively to build BSP-style layouts such
+/// as dwindle or spiral.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region(pub Rectangle);
+
+impl Region {
+    /// Split the region in two. The first region gets `ratio` of the space and is placed on the
+    /// side given by `orientation`, the second region gets the rest, so together they cover
+    /// `self` exactly.
+    pub fn split(&self, ratio: f64, orientation: Orientation) -> (Region, Region) {
+        let (first, second) = orientation.split(self.0, ratio);
+        (Region(first), Region(second))
+    }
+
+    /// Arrange `view_count` views by halving the remaining space again and again, alternating
+    /// between vertical and horizontal splits.
+    pub fn dwindle(&self, view_count: u32) -> Vec<Rectangle> {
+        let mut views = Vec::with_capacity(view_count as usize);
+        let mut rest = *self;
+        for i in 1..view_count {
+            let orientation = if i % 2 == 1 {
+                Orientation::Left
+            } else {
+                Orientation::Top
+            };
+            let (first, second) = rest.split(0.5, orientation);
+            views.push(first.0);
+            rest = second;
+        }
+        if view_count > 0 {
+            views.push(rest.0);
+        }
+        views
+    }
+}
+
+impl From<Rectangle> for Region {
+    fn from(rect: Rectangle) -> Self {
+        Self(rect)
+    }
+}
+
+impl From<Region> for Rectangle {
+    fn from(region: Region) -> Self {
+        region.0
+    }
+}
+
+fn swap<T>((a, b): (T, T)) -> (T, T) {
+    (b, a)
+}
+
+/// Split `area` into `n` columns (if `horizontal`) or rows, first ones getting the leftover
+/// pixels.
+fn tile(area: Rectangle, n: u32, horizontal: bool) -> impl Iterator<Item = Rectangle> {
+    let total = if horizontal { area.width } else { area.height };
+    (0..n).filter_map(move |i| {
+        let (offset, len) = checked_split(total, n, i)?;
+        Some(if horizontal {
+            Rectangle::new(
+                area.x.saturating_add_unsigned(offset),
+                area.y,
+                len,
+                area.height,
+            )
+        } else {
+            Rectangle::new(
+                area.x,
+                area.y.saturating_add_unsigned(offset),
+                area.width,
+                len,
+            )
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OutputInfo, Outputs, Tags};
+
+    fn ctx(output: &OutputInfo, view_count: u32, width: u32, height: u32) -> LayoutContext<'_> {
+        LayoutContext {
+            view_count,
+            usable_width: width,
+            usable_height: height,
+            tags: Tags(1),
+            output,
+            protocol_version: 2,
+            serial: 0,
+            outputs: Outputs::List(std::slice::from_ref(output)),
+            previous_layout: None,
+            previous_tags: None,
+        }
+    }
+
+    /// Assert that `views` cover `area` exactly, without overlapping.
+    fn assert_tiles(views: &[Rectangle], area: Rectangle) {
+        for (i, a) in views.iter().enumerate() {
+            assert_eq!(a.intersection(&area), Some(*a), "{a:?} is out of bounds");
+            for b in &views[i + 1..] {
+                assert_eq!(a.intersection(b), None, "{a:?} overlaps {b:?}");
+            }
+        }
+        assert_eq!(views.iter().map(Rectangle::area).sum::<u64>(), area.area());
+    }
+
+    fn grids() -> impl Iterator<Item = Grid> {
+        [FillOrder::RowMajor, FillOrder::ColumnMajor]
+            .into_iter()
+            .flat_map(|fill_order| {
+                [GridOrientation::Rows, GridOrientation::Columns]
+                    .into_iter()
+                    .map(move |orientation| Grid {
+                        fill_order,
+                        orientation,
+                    })
+            })
+    }
+
+    /// Every layout with the usable area at its maximum. Layouts allocate a rectangle per view, so
+    /// the view counts stay small; the math tests cover huge counts.
+    #[test]
+    fn extreme_dimensions() {
+        let output = OutputInfo::new("test");
+        let max = u32::MAX;
+        let orientations = [
+            Orientation::Left,
+            Orientation::Right,
+            Orientation::Top,
+            Orientation::Bottom,
+        ];
+        for (width, height) in [(max, max), (max, 0), (0, max), (max, 1)] {
+            for view_count in [0, 1, 2, 3, 4, 17] {
+                let ctx = ctx(&output, view_count, width, height);
+                let n = view_count as usize;
+                for orientation in orientations {
+                    for main_count in [0, 1, 2, max] {
+                        for main_ratio in [0.0, 0.6, 1.0] {
+                            let layout = MasterStack {
+                                main_count,
+                                main_ratio,
+                                orientation,
+                            };
+                            assert_eq!(layout.layout(&ctx).views.len(), n);
+                        }
+                    }
+                    let (main, stack) = orientation.split(ctx.usable_area(), 0.5);
+                    assert_eq!(main.area() + stack.area(), ctx.usable_area().area());
+                }
+                for ratio in [0.0, 0.5, 1.0] {
+                    let layout = CenteredMaster {
+                        main_ratio: ratio,
+                        side_ratio: ratio,
+                    };
+                    assert_eq!(layout.layout(&ctx).views.len(), n);
+                }
+                for grid in grids() {
+                    assert_eq!(grid.layout(&ctx).views.len(), n);
+                }
+                assert_eq!(Monocle.layout(&ctx).views.len(), n);
+                assert_eq!(Region(ctx.usable_area()).dwindle(view_count).len(), n);
+            }
+        }
+    }
+
+    #[test]
+    fn grid_covers_area() {
+        let output = OutputInfo::new("test");
+        for (width, height) in [(1920, 1080), (1081, 1921), (100, 7)] {
+            for view_count in 1..=10 {
+                let ctx = ctx(&output, view_count, width, height);
+                for grid in grids() {
+                    let views = grid.layout(&ctx).views;
+                    assert_eq!(views.len(), view_count as usize, "{grid:?}");
+                    assert_tiles(&views, ctx.usable_area());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn grid_fill_order() {
+        let output = OutputInfo::new("test");
+        let ctx = ctx(&output, 3, 100, 100);
+        let grid = |fill_order, orientation| {
+            let grid = Grid {
+                fill_order,
+                orientation,
+            };
+            grid.layout(&ctx).views
+        };
+        let (top_left, top_right) = (Rectangle::new(0, 0, 50, 50), Rectangle::new(50, 0, 50, 50));
+        let bottom = Rectangle::new(0, 50, 100, 50);
+        assert_eq!(
+            grid(FillOrder::RowMajor, GridOrientation::Rows),
+            [top_left, top_right, bottom]
+        );
+        assert_eq!(
+            grid(FillOrder::ColumnMajor, GridOrientation::Rows),
+            [top_left, bottom, top_right]
+        );
+        let (left_top, left_bottom) = (top_left, Rectangle::new(0, 50, 50, 50));
+        let right = Rectangle::new(50, 0, 50, 100);
+        assert_eq!(
+            grid(FillOrder::ColumnMajor, GridOrientation::Columns),
+            [left_top, left_bottom, right]
+        );
+        assert_eq!(
+            grid(FillOrder::RowMajor, GridOrientation::Columns),
+            [left_top, right, left_bottom]
+        );
+    }
+
+    #[test]
+    fn split_leftover() {
+        let area = Rectangle::new(0, 0, 101, 101);
+        let split = |orientation: Orientation| orientation.split(area, 0.5);
+        let (left, right) = (
+            Rectangle::new(0, 0, 50, 101),
+            Rectangle::new(50, 0, 51, 101),
+        );
+        let (top, bottom) = (
+            Rectangle::new(0, 0, 101, 50),
+            Rectangle::new(0, 50, 101, 51),
+        );
+        assert_eq!(split(Orientation::Left), (left, right));
+        assert_eq!(split(Orientation::Right), (right, left));
+        assert_eq!(split(Orientation::Top), (top, bottom));
+        assert_eq!(split(Orientation::Bottom), (bottom, top));
+    }
+
+    #[test]
+    fn centered_master() {
+        let output = OutputInfo::new("test");
+        let layout = CenteredMaster::default();
+        let views = |view_count| layout.layout(&ctx(&output, view_count, 1001, 600)).views;
+        let area = Rectangle::new(0, 0, 1001, 600);
+
+        assert!(views(0).is_empty());
+        assert_eq!(views(1), [area]);
+        // With two views, there is no right stack.
+        assert_eq!(
+            views(2),
+            [
+                Rectangle::new(0, 0, 500, 600),
+                Rectangle::new(500, 0, 501, 600)
+            ]
+        );
+        // The right stack gets the first and, with an odd count, the extra view.
+        assert_eq!(
+            views(4),
+            [
+                Rectangle::new(250, 0, 500, 600),
+                Rectangle::new(750, 0, 251, 300),
+                Rectangle::new(750, 300, 251, 300),
+                Rectangle::new(0, 0, 250, 600),
+            ]
+        );
+        for view_count in 1..=10 {
+            let views = views(view_count);
+            assert_eq!(views.len(), view_count as usize);
+            assert_tiles(&views, area);
+        }
+    }
+}
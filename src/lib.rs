@@ -2,151 +2,2283 @@
 #![warn(clippy::semicolon_if_nothing_returned)]
 #![warn(clippy::unnecessary_wraps)]
 
+use std::collections::HashMap;
+use std::env;
 use std::error::Error as StdError;
 use std::ffi::CString;
+use std::fmt;
 use std::io;
+use std::marker::PhantomData;
+use std::mem;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, IntoRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use wayrs_client::global::{Global, GlobalExt};
+use wayrs_client::object::Proxy;
 use wayrs_client::protocol::*;
-use wayrs_client::{Connection, EventCtx, IoMode};
+use wayrs_client::{ConnectError, Connection, EventCtx, IoMode};
 
 wayrs_client::generate!("river-layout-v3.xml");
 
+mod boxed;
+pub mod command;
+#[cfg(feature = "control-socket")]
+mod control;
+#[cfg(feature = "calloop")]
+mod event_source;
+#[cfg(feature = "external")]
+pub mod external;
+mod fn_layout;
+mod layout_set;
+pub mod layouts;
+pub mod math;
+#[cfg(any(test, feature = "test-util"))]
+pub mod mock;
+mod per_output;
+mod poll;
+mod rectangle;
+#[cfg(feature = "signals")]
+mod signals;
+mod stateful;
+mod stop;
+mod store;
+mod tags;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod validation;
+
+pub use boxed::{BoxedError, BoxedLayout, ErasedLayout};
+#[cfg(feature = "calloop")]
+pub use event_source::LayoutSource;
+pub use fn_layout::FnLayout;
+pub use layout_set::LayoutSet;
+pub use per_output::PerOutput;
+pub use rectangle::{FloatRect, Rectangle};
+/// Derive macro for [`Layout`], available with the `derive` feature.
+#[cfg(feature = "derive")]
+pub use river_layout_toolkit_derive::Layout;
+pub use stateful::{InvalidCommand, LayoutState, SmartGaps, Stateful, StatefulLayout};
+pub use stop::StopHandle;
+pub use store::{default_state_dir, StateStore};
+pub use tags::Tags;
+pub use validation::{ValidationError, ValidationOptions};
+
 /// This trait represents a layout generator implementation.
 pub trait Layout: 'static {
     /// The error type of [`user_cmd`](Self::user_cmd) and [`generate_layout`](Self::generate_layout)
     /// functions. Use [`Infallible`](std::convert::Infallible) if you don't need it.
     type Error: StdError;
 
-    /// The namespace is used by the compositor to distinguish between layout generators. Two separate
-    /// clients may not share a namespace. Otherwise, [`run`] will return [`Error::NamespaceInUse`].
-    const NAMESPACE: &'static str;
+    /// The namespace is used by the compositor to distinguish between layout generators. Two separate
+    /// clients may not share a namespace. Otherwise, [`run`] will return [`Error::NamespaceInUse`].
+    const NAMESPACE: &'static str;
+
+    /// The namespace to register with the compositor. Override this to choose the namespace at
+    /// runtime, e.g. from a command line argument. Defaults to [`NAMESPACE`](Self::NAMESPACE).
+    ///
+    /// This function is called once, before [`on_start`](Self::on_start).
+    fn namespace(&self) -> &str {
+        Self::NAMESPACE
+    }
+
+    /// This function is called whenever the user sends a command via `riverctl send-layout-cmd`.
+    ///
+    /// Return [`UserCmdOutcome::RequestLayout`] if the command changed the layout. The default
+    /// implementation ignores all commands, so layouts without commands do not need to implement
+    /// this function, whatever their [`Error`](Self::Error) type is.
+    ///
+    /// `tags` are the focused tags of the output at the time the command was sent. They are only
+    /// known since version 2 of the protocol and are always `None` with version 1.
+    ///
+    /// # Errors
+    ///
+    /// An error returned from this function is logged, and only stops the layout generator if
+    /// [`RunOptions::fatal_user_cmd_errors`] is set.
+    fn user_cmd(
+        &mut self,
+        _cmd: String,
+        _tags: Option<Tags>,
+        _output: &str,
+    ) -> Result<UserCmdOutcome, Self::Error> {
+        Ok(UserCmdOutcome::Handled)
+    }
+
+    /// This function is called whenever compositor requests a layout.
+    ///
+    /// # Errors
+    ///
+    /// The demand is answered anyway: with the previous layout of the output if it still has the
+    /// right number of views, or without any views otherwise. Then [`run`] stops with
+    /// [`Error::LayoutError`], unless [`RunOptions::on_generate_error`] is set to
+    /// [`ErrorPolicy::Warn`], in which case the error is logged and the next demand is handled as
+    /// usual.
+    fn generate_layout(&mut self, ctx: &LayoutContext<'_>) -> Result<GeneratedLayout, Self::Error>;
+
+    /// A push-based alternative to [`generate_layout`](Self::generate_layout), which writes the
+    /// views into a buffer reused between demands instead of allocating a new [`Vec`] each time.
+    ///
+    /// The default implementation calls [`generate_layout`](Self::generate_layout). If you
+    /// override this function, `generate_layout` is not called by [`run`].
+    ///
+    /// # Errors
+    ///
+    /// Errors are handled like those of [`generate_layout`](Self::generate_layout), according to
+    /// [`RunOptions::on_generate_error`]. Views pushed before the error are discarded.
+    fn generate_layout_into(&mut self, demand: &mut Demand<'_>) -> Result<(), Self::Error> {
+        let generated_layout = self.generate_layout(demand.ctx())?;
+        *demand.views = generated_layout.views;
+        *demand.layout_name = generated_layout.layout_name;
+        Ok(())
+    }
+
+    /// A streaming alternative to [`generate_layout_into`](Self::generate_layout_into), for
+    /// layouts with many views: every view pushed to the [`ViewStream`] is sent to the compositor
+    /// right away, without collecting the views first, and the layout is committed once this
+    /// function returns.
+    ///
+    /// The default implementation returns `None`, in which case `generate_layout_into` is called
+    /// instead. Returning `None` after pushing views is a bug: the views have already been sent,
+    /// so the demand cannot be answered anymore and [`run`] stops with
+    /// [`Error::StreamAbandoned`].
+    ///
+    /// Sent views cannot be taken back, so the demand is answered with whatever was pushed:
+    ///
+    /// - Views beyond [`view_count`](LayoutContext::view_count) are never sent. Pushing too many
+    ///   or too few views is handled according to [`RunOptions::on_view_count_mismatch`], once
+    ///   this function returns or, with [`ViewCountCheck::OnPush`], as soon as an extra view is
+    ///   pushed. Layouts can check [`ViewStream::is_finished`] to stop early.
+    /// - Other problems, including errors returned from this function, are handled according to
+    ///   their [`RunOptions`], but the layout is committed without a name instead of falling back
+    ///   to the previous layout.
+    /// - [`RunOptions::detect_overlap`] does not apply, and
+    ///   [`LayoutContext::previous_layout`] is `None` after a streamed layout.
+    ///
+    /// # Errors
+    ///
+    /// The pushed views have already been sent, so the demand is committed with them, without a
+    /// name. Then [`run`] stops with [`Error::LayoutError`], unless
+    /// [`RunOptions::on_generate_error`] is set to [`ErrorPolicy::Warn`], in which case the error
+    /// is logged.
+    fn stream_layout(&mut self, _stream: &mut ViewStream<'_>) -> Option<Result<(), Self::Error>> {
+        None
+    }
+
+    /// This function is called once the connection to the compositor is established and the
+    /// layout manager is bound, after [`namespace`](Self::namespace) and
+    /// [`set_state_path`](Self::set_state_path) but before any output is added, command received
+    /// or layout generated. With [`RunOptions::wait_for_layout_manager`], it is called before
+    /// waiting for the layout manager instead.
+    ///
+    /// With [`RunOptions::auto_reconnect`], it is called again after every reconnection.
+    ///
+    /// # Errors
+    ///
+    /// An error is not handled by [`RunOptions::on_generate_error`]: no layout can be generated
+    /// without starting, so [`LayoutRunnerBuilder::build`] (and thus [`run`]) returns it as
+    /// [`Error::LayoutError`]. After a reconnection, [`LayoutRunner::run`] returns it instead of
+    /// continuing.
+    fn on_start(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// This function is called once, before [`on_start`](Self::on_start), with the file where the
+    /// layout may persist its state between runs: `<namespace>.json` in
+    /// [`RunOptions::state_dir`]. It is not called if no state directory could be determined.
+    ///
+    /// See [`StateStore`] for a ready-made way to store the state.
+    fn set_state_path(&mut self, _path: &Path) {}
+
+    /// This function is called once when the layout generator stops. Use it to persist state.
+    ///
+    /// That is before shutting down gracefully, e.g. after receiving SIGTERM with the `signals`
+    /// feature enabled, but also when [`run`] returns an error, including after running out of
+    /// reconnection attempts, and when a [`LayoutRunner`] is dropped without being run. It is not
+    /// called if the process exits without unwinding, or while panicking.
+    fn on_shutdown(&mut self) {}
+
+    /// This function is called when a new output appears, as soon as its name is known.
+    fn output_added(&mut self, _output: &str) {}
+
+    /// This function is called when an output goes away. Use it to clean up per-output state.
+    fn output_removed(&mut self, _output: &str) {}
+
+    /// This function is called before a layout is generated for a demand whose usable area, as
+    /// `(width, height)`, differs from the previous demand on the same output. Use it to
+    /// invalidate data which depends on the size of the output.
+    ///
+    /// On the first demand of an output, `old` is `None`.
+    fn usable_area_changed(&mut self, _output: &str, _old: Option<(u32, u32)>, _new: (u32, u32)) {}
+
+    /// Erase the type of the layout, see [`BoxedLayout`].
+    fn boxed(self) -> BoxedLayout
+    where
+        Self: Sized,
+        Self::Error: Send + Sync,
+    {
+        BoxedLayout::new(self)
+    }
+}
+
+/// What the layout generator should do after a [`Layout::user_cmd`] call.
+///
+/// The protocol does not allow clients to request a new layout. Instead, river sends a layout
+/// demand after every user command if this layout generator is currently in use on the output,
+/// so a [`RequestLayout`](Self::RequestLayout) is answered right away without any extra
+/// round-trips.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserCmdOutcome {
+    /// The command was handled and the current layout is still valid.
+    Handled,
+    /// The command changed the layout.
+    ///
+    /// This does not make the runner do anything, apart from logging at the debug level: the
+    /// protocol has no request for a new layout, so the new layout is only applied on the next
+    /// layout demand, which river sends after every user command. Commands from the control
+    /// socket, available with the `control-socket` feature, are not followed by a demand, so
+    /// their changes wait for the next change of the views or the output.
+    RequestLayout,
+}
+
+/// The parameters of a layout demand, passed to [`Layout::generate_layout`].
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct LayoutContext<'a> {
+    /// The number of views to generate rectangles for.
+    pub view_count: u32,
+    /// The width of the area in which views can be positioned.
+    pub usable_width: u32,
+    /// The height of the area in which views can be positioned.
+    pub usable_height: u32,
+    /// The focused tags of the output.
+    pub tags: Tags,
+    /// The latest known properties of the output, see [`OutputInfo`].
+    pub output: &'a OutputInfo,
+    /// The negotiated version of the `river_layout_manager_v3` protocol, see
+    /// [`LayoutRunner::protocol_version`].
+    pub protocol_version: u32,
+    /// The serial of the layout demand. It is opaque and only useful for correlating log messages
+    /// with the compositor.
+    pub serial: u32,
+    pub(crate) outputs: Outputs<'a>,
+    pub(crate) previous_layout: Option<&'a GeneratedLayout>,
+    pub(crate) previous_tags: Option<Tags>,
+}
+
+impl<'a> LayoutContext<'a> {
+    /// The usable area as a rectangle at `(0, 0)`, the starting point for splitting it into
+    /// views.
+    pub fn usable_area(&self) -> Rectangle {
+        Rectangle::new(0, 0, self.usable_width, self.usable_height)
+    }
+
+    /// The focused tags of the previous layout demand on this output, `None` for the first one.
+    pub fn previous_tags(&self) -> Option<Tags> {
+        self.previous_tags
+    }
+
+    /// Whether the focused tags differ from the previous layout demand on this output. This is
+    /// `true` for the first demand.
+    pub fn tags_changed(&self) -> bool {
+        self.previous_tags != Some(self.tags)
+    }
+
+    /// The layout last committed on this output, if any.
+    pub fn previous_layout(&self) -> Option<&'a GeneratedLayout> {
+        self.previous_layout
+    }
+
+    /// The name of the layout last committed on the given output, if any. This is the name of
+    /// [`previous_layout`](Self::previous_layout) for the output of the demand.
+    pub fn current_layout_name(&self, output: &str) -> Option<&'a str> {
+        if output == self.output.name {
+            return self
+                .previous_layout
+                .map(|layout| layout.layout_name.as_str());
+        }
+        match self.outputs {
+            Outputs::Connected(outputs, slot) => outputs
+                .values()
+                .find(|o| o.info.name == output)?
+                .river_layouts
+                .get(slot)?
+                .last_layout
+                .as_ref()
+                .map(|layout| layout.layout_name.as_str()),
+            // Only the layout of the demanded output is known in tests.
+            Outputs::List(_) => None,
+        }
+    }
+
+    /// All outputs known at the time of the demand, including [`output`](Self::output). Outputs
+    /// are listed once their name is known, in no particular order.
+    ///
+    /// The outputs are borrowed from the runner, not from the layout, so they can be used while
+    /// the layout is mutated:
+    ///
+    /// ```no_run
+    /// # use river_layout_toolkit::{GeneratedLayout, Layout, LayoutContext};
+    /// # use std::convert::Infallible;
+    /// # use std::collections::HashMap;
+    /// struct MyLayout {
+    ///     widest: HashMap<String, i32>,
+    /// }
+    ///
+    /// impl Layout for MyLayout {
+    ///     # type Error = Infallible;
+    ///     # const NAMESPACE: &'static str = "my-layout";
+    ///     fn generate_layout(&mut self, ctx: &LayoutContext<'_>) -> Result<GeneratedLayout, Infallible> {
+    ///         let outputs: Vec<_> = ctx.outputs().collect();
+    ///         let widest = outputs.iter().filter_map(|o| o.mode).map(|m| m.width).max();
+    ///         self.widest.insert(ctx.output.name.clone(), widest.unwrap_or(0));
+    ///         // `outputs` is still usable here.
+    ///         # let _ = outputs;
+    ///         # todo!()
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// See [`PerOutput::split_mut`] for reading the state of other outputs while mutating the one
+    /// of the demanded output.
+    pub fn outputs(&self) -> impl Iterator<Item = &'a OutputInfo> + 'a {
+        let (connected, test_outputs) = match self.outputs {
+            Outputs::Connected(outputs, _) => (Some(outputs), &[][..]),
+            Outputs::List(outputs) => (None, outputs),
+        };
+        connected
+            .into_iter()
+            .flat_map(|outputs| outputs.values())
+            .filter(|output| !output.river_layouts.is_empty())
+            .map(|output| &output.info)
+            .chain(test_outputs)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum Outputs<'a> {
+    /// The outputs of the runner and the index of the layout which handles the demand.
+    Connected(&'a HashMap<WlOutput, Output>, usize),
+    #[cfg_attr(not(feature = "testing"), allow(dead_code))]
+    List(&'a [OutputInfo]),
+}
+
+impl fmt::Debug for Outputs<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = match self {
+            Self::Connected(outputs, _) => outputs.values().map(|o| o.info.name.as_str()).collect(),
+            Self::List(outputs) => outputs.iter().map(|o| o.name.as_str()).collect(),
+        };
+        f.debug_list().entries(names).finish()
+    }
+}
+
+/// A layout demand to be answered by pushing views, see [`Layout::generate_layout_into`].
+#[derive(Debug)]
+pub struct Demand<'a> {
+    ctx: LayoutContext<'a>,
+    views: &'a mut Vec<Rectangle>,
+    layout_name: &'a mut String,
+}
+
+impl<'a> Demand<'a> {
+    /// Create a demand which writes into the given buffers. The buffers are cleared.
+    pub(crate) fn new(
+        ctx: LayoutContext<'a>,
+        views: &'a mut Vec<Rectangle>,
+        layout_name: &'a mut String,
+    ) -> Self {
+        views.clear();
+        layout_name.clear();
+        Self {
+            ctx,
+            views,
+            layout_name,
+        }
+    }
+
+    /// The parameters of this demand.
+    pub fn ctx(&self) -> &LayoutContext<'a> {
+        &self.ctx
+    }
+
+    /// Add the next view to the layout.
+    pub fn push_view(&mut self, view: Rectangle) {
+        self.views.push(view);
+    }
+
+    /// Set the name of the layout. Empty by default.
+    ///
+    /// The name is copied into a buffer reused between demands. The only allocation of a demand
+    /// answered this way is the copy of the name sent to the compositor.
+    pub fn set_layout_name(&mut self, name: &str) {
+        self.layout_name.clear();
+        self.layout_name.push_str(name);
+    }
+}
+
+/// A layout demand answered by sending each view to the compositor as soon as it is pushed, see
+/// [`Layout::stream_layout`].
+pub struct ViewStream<'a> {
+    ctx: LayoutContext<'a>,
+    sink: &'a mut dyn FnMut(usize, Rectangle),
+    pushed: usize,
+    layout_name: String,
+    finished: bool,
+    /// The number of views after which the stream ends, see [`ViewCountCheck::OnPush`].
+    limit: Option<usize>,
+}
+
+impl<'a> ViewStream<'a> {
+    /// Create a stream which passes each view and its index to `sink`.
+    pub(crate) fn new(ctx: LayoutContext<'a>, sink: &'a mut dyn FnMut(usize, Rectangle)) -> Self {
+        Self {
+            ctx,
+            sink,
+            pushed: 0,
+            layout_name: String::new(),
+            finished: false,
+            limit: None,
+        }
+    }
+
+    /// End the stream as soon as more than `limit` views are pushed.
+    pub(crate) fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// The parameters of this demand.
+    pub fn ctx(&self) -> &LayoutContext<'a> {
+        &self.ctx
+    }
+
+    /// Send the next view to the compositor.
+    pub fn push(&mut self, view: Rectangle) {
+        if self.finished {
+            return;
+        }
+        if self.limit == Some(self.pushed) {
+            // Count the extra view, so that the mismatch is reported, but do not send it.
+            self.pushed += 1;
+            self.finished = true;
+            return;
+        }
+        (self.sink)(self.pushed, view);
+        self.pushed += 1;
+    }
+
+    /// Whether views pushed from now on are ignored, because [`finish`](Self::finish) was called
+    /// or, with [`ViewCountCheck::OnPush`], too many views were pushed.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// The number of views pushed so far.
+    pub fn pushed(&self) -> usize {
+        self.pushed
+    }
+
+    /// Set the name of the layout and end the stream. Views pushed afterwards are ignored. The
+    /// layout is committed without a name if this function is not called.
+    pub fn finish(&mut self, name: &str) {
+        self.layout_name.clear();
+        self.layout_name.push_str(name);
+        self.finished = true;
+    }
+}
+
+impl fmt::Debug for ViewStream<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ViewStream")
+            .field("ctx", &self.ctx)
+            .field("pushed", &self.pushed)
+            .field("layout_name", &self.layout_name)
+            .field("finished", &self.finished)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Properties of an output, as reported by the compositor.
+///
+/// The compositor may send these properties at any time, so everything except `name` may change
+/// between two layout demands. Changes are applied together once the compositor has sent all of
+/// them, so the properties always describe the same state of the output.
+#[derive(Debug, Clone)]
+pub struct OutputInfo {
+    /// The name of the output, e.g. `eDP-1`.
+    ///
+    /// Compositors which do not support version 4 of `wl_output` do not report names, in which
+    /// case a name like `wl_output-42` is made up from the registry name of the output.
+    ///
+    /// Names which are not valid UTF-8 are converted lossily, with invalid sequences replaced by
+    /// `U+FFFD REPLACEMENT CHARACTER`.
+    pub name: String,
+    /// The manufacturer of the output. Falls back to `name` if the compositor does not report it.
+    pub make: String,
+    /// The model of the output. Falls back to `name` if the compositor does not report it.
+    pub model: String,
+    /// The integer scale factor of the output. This is `1` until the compositor reports otherwise.
+    pub scale: i32,
+    /// The physical properties of the output. This is `None` until the compositor reports them.
+    pub geometry: Option<OutputGeometry>,
+    /// The current mode of the output. This is `None` until the compositor reports it.
+    pub mode: Option<OutputMode>,
+    /// The rotation of the output. This is [`OutputTransform::Normal`] until the compositor
+    /// reports otherwise.
+    pub transform: OutputTransform,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputGeometry {
+    /// X position within the global compositor space.
+    pub x: i32,
+    /// Y position within the global compositor space.
+    pub y: i32,
+    /// Width in millimeters. May be `0` if it does not make sense for this output.
+    pub physical_width: i32,
+    /// Height in millimeters. May be `0` if it does not make sense for this output.
+    pub physical_height: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputMode {
+    /// Width in hardware units.
+    pub width: i32,
+    /// Height in hardware units.
+    pub height: i32,
+    /// Refresh rate in mHz.
+    pub refresh: i32,
+}
+
+/// How the contents of an output are rotated and flipped, counter-clockwise. A layout may use it to
+/// pick an orientation for rotated monitors, although the usable area already has the rotated size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum OutputTransform {
+    #[default]
+    Normal,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    Flipped,
+    Flipped90,
+    Flipped180,
+    Flipped270,
+}
+
+impl OutputTransform {
+    /// Whether the output is rotated by 90 or 270 degrees, i.e. whether its width and height are
+    /// swapped.
+    pub fn is_rotated(self) -> bool {
+        matches!(
+            self,
+            Self::Rotate90 | Self::Rotate270 | Self::Flipped90 | Self::Flipped270
+        )
+    }
+
+    fn from_wayland(transform: wl_output::Transform) -> Self {
+        match transform {
+            wl_output::Transform::_90 => Self::Rotate90,
+            wl_output::Transform::_180 => Self::Rotate180,
+            wl_output::Transform::_270 => Self::Rotate270,
+            wl_output::Transform::Flipped => Self::Flipped,
+            wl_output::Transform::Flipped90 => Self::Flipped90,
+            wl_output::Transform::Flipped180 => Self::Flipped180,
+            wl_output::Transform::Flipped270 => Self::Flipped270,
+            _ => Self::Normal,
+        }
+    }
+}
+
+impl OutputInfo {
+    /// Create an `OutputInfo` with the given name and all other properties unknown.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            make: String::new(),
+            model: String::new(),
+            scale: 1,
+            geometry: None,
+            mode: None,
+            transform: OutputTransform::Normal,
+        }
+    }
+
+    fn fill_make_model(&mut self) {
+        if self.make.is_empty() {
+            self.make.clone_from(&self.name);
+        }
+        if self.model.is_empty() {
+            self.model.clone_from(&self.name);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GeneratedLayout {
+    pub layout_name: String,
+    pub views: Vec<Rectangle>,
+}
+
+fn check_view_count<E: StdError>(
+    views: &[Rectangle],
+    view_count: u32,
+    output: &str,
+) -> Result<(), Error<E>> {
+    if views.len() == view_count as usize {
+        Ok(())
+    } else {
+        Err(Error::InvalidGeneratedLayout {
+            expected: view_count,
+            got: views.len(),
+            output: output.into(),
+        })
+    }
+}
+
+/// Apply [`RunOptions::on_zero_size_view`], [`RunOptions::snap_to_scale`] and
+/// [`RunOptions::clamp_to_usable`] to a generated view. Zero sizes are checked first, since
+/// clamping turns them into a width or height of `1`.
+fn adjust_view<E: StdError>(
+    options: &RunOptions,
+    usable: &Rectangle,
+    index: usize,
+    rect: &mut Rectangle,
+    output: &str,
+) -> Result<(), Error<E>> {
+    if rect.width == 0 || rect.height == 0 {
+        match options.on_zero_size_view {
+            Some(ErrorPolicy::Error) => {
+                return Err(Error::ZeroSizeView {
+                    index,
+                    output: output.into(),
+                });
+            }
+            Some(ErrorPolicy::Warn) => {
+                log::warn!("view {index} on output '{output}' has a zero size: {rect:?}");
+            }
+            None => (),
+        }
+    }
+    if let Some(scale) = options.snap_to_scale {
+        *rect = rect.snap_to_scale_within(scale, Some(usable));
+    }
+    if options.clamp_to_usable && !rect.is_within(usable) {
+        let clamped = rect.clamp_to(usable);
+        log::warn!(
+            "view {index} on output '{output}' is out of bounds: clamped {rect:?} to {clamped:?}"
+        );
+        *rect = clamped;
+    }
+    Ok(())
+}
+
+/// Send a view to the compositor. The exact coordinates are logged at the debug level, for
+/// diagnosing layouts which look wrong on screen.
+fn push_view<L: Layout>(
+    conn: &mut Connection<RunnerState<L>>,
+    river: RiverLayoutV3,
+    output: &str,
+    index: usize,
+    rect: &Rectangle,
+    serial: u32,
+) {
+    log::debug!(
+        "output '{output}', serial {serial}: view {index} at x={} y={} width={} height={}",
+        rect.x,
+        rect.y,
+        rect.width,
+        rect.height,
+    );
+    river.push_view_dimensions(conn, rect.x, rect.y, rect.width, rect.height, serial);
+}
+
+/// Answer a demand with [`Layout::stream_layout`]. Returns `None` if the layout does not stream,
+/// otherwise the error to stop with, if any. The demand is committed either way.
+fn stream_demand<L: Layout>(
+    conn: &mut Connection<RunnerState<L>>,
+    river: RiverLayoutV3,
+    layout: &mut L,
+    ctx: LayoutContext<'_>,
+    options: &RunOptions,
+    views_buf: &mut Vec<Rectangle>,
+) -> Option<Result<(), Error<L::Error>>> {
+    let (view_count, serial, output) = (ctx.view_count, ctx.serial, &ctx.output.name);
+    let usable = ctx.usable_area();
+    let mut view_error = None;
+    views_buf.clear();
+    let mut sink = |index: usize, mut rect: Rectangle| {
+        if index >= view_count as usize || view_error.is_some() {
+            return;
+        }
+        if let Err(err) = adjust_view(options, &usable, index, &mut rect, output) {
+            view_error = Some(err);
+        } else if options.dry_run {
+            views_buf.push(rect);
+        } else {
+            push_view(conn, river, output, index, &rect, serial);
+        }
+    };
+    let mut stream = ViewStream::new(ctx, &mut sink);
+    if options.view_count_check == ViewCountCheck::OnPush {
+        stream = stream.with_limit(view_count as usize);
+    }
+    let result = layout.stream_layout(&mut stream);
+    let (pushed, layout_name) = (stream.pushed, mem::take(&mut stream.layout_name));
+
+    // `Err(None)` is a recoverable error, which has already been logged.
+    let result: Result<CString, Option<Error<L::Error>>> = 'demand: {
+        let result = match result {
+            Some(result) => result,
+            None if pushed == 0 => return None,
+            None => {
+                break 'demand Err(Some(Error::StreamAbandoned {
+                    pushed,
+                    output: output.clone(),
+                }))
+            }
+        };
+        if let Err(e) = result {
+            break 'demand match options.on_generate_error {
+                ErrorPolicy::Error => Err(Some(Error::LayoutError(e))),
+                ErrorPolicy::Warn => {
+                    log::warn!("stream_layout error on output '{output}': {e}");
+                    Err(None)
+                }
+            };
+        }
+        if let Some(err) = view_error {
+            break 'demand Err(Some(err));
+        }
+        if pushed != view_count as usize {
+            let err = Error::ViewCountMismatch {
+                expected: view_count,
+                pushed,
+                output: output.clone(),
+            };
+            match options.on_view_count_mismatch {
+                Policy::Truncate if pushed > view_count as usize => (),
+                Policy::Warn => log::warn!("{err}"),
+                _ => break 'demand Err(Some(err)),
+            }
+        }
+        CString::new(layout_name.as_str()).map_err(|_| Some(Error::InvalidLayoutName(layout_name)))
+    };
+
+    match result {
+        Ok(_) if options.dry_run => {
+            println!("output '{output}', serial {serial}: streamed layout");
+            for (i, rect) in views_buf.iter().enumerate() {
+                println!(
+                    "  {i}: {}x{} at {},{}",
+                    rect.width, rect.height, rect.x, rect.y
+                );
+            }
+            river.commit(conn, CString::default(), serial);
+            Some(Ok(()))
+        }
+        Ok(layout_name) => {
+            river.commit(conn, layout_name, serial);
+            Some(Ok(()))
+        }
+        Err(err) => {
+            river.commit(conn, CString::default(), serial);
+            Some(err.map_or(Ok(()), Err))
+        }
+    }
+}
+
+fn check_overlap<E: StdError>(views: &[Rectangle], output: &str) -> Result<(), Error<E>> {
+    let overlaps: Vec<_> = validation::overlaps(views).collect();
+    if overlaps.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::OverlappingViews {
+            overlaps,
+            output: output.into(),
+        })
+    }
+}
+
+impl GeneratedLayout {
+    pub fn builder(layout_name: impl Into<String>) -> GeneratedLayoutBuilder {
+        GeneratedLayoutBuilder {
+            layout: GeneratedLayout {
+                layout_name: layout_name.into(),
+                views: Vec::new(),
+            },
+        }
+    }
+
+    /// Create a layout from an iterator of views.
+    ///
+    /// To build the views with iterator combinators and set the name separately, `collect()` into
+    /// a [`GeneratedLayout`] instead, which leaves the name empty.
+    pub fn from_views(
+        layout_name: impl Into<String>,
+        views: impl IntoIterator<Item = Rectangle>,
+    ) -> Self {
+        Self {
+            layout_name: layout_name.into(),
+            views: views.into_iter().collect(),
+        }
+    }
+
+    /// Replace the name of the layout. The name is only read when the layout is committed, so it
+    /// may be set after the views are known.
+    pub fn set_name(&mut self, layout_name: impl Into<String>) {
+        self.layout_name = layout_name.into();
+    }
+
+    /// Whether the layout has exactly `view_count` views, as required by the compositor.
+    pub fn has_view_count(&self, view_count: u32) -> bool {
+        self.views.len() == view_count as usize
+    }
+}
+
+impl FromIterator<Rectangle> for GeneratedLayout {
+    fn from_iter<I: IntoIterator<Item = Rectangle>>(iter: I) -> Self {
+        Self::from_views(String::new(), iter)
+    }
+}
+
+impl Extend<Rectangle> for GeneratedLayout {
+    fn extend<I: IntoIterator<Item = Rectangle>>(&mut self, iter: I) {
+        self.views.extend(iter);
+    }
+}
+
+/// A builder for [`GeneratedLayout`], see [`GeneratedLayout::builder`].
+#[derive(Debug)]
+pub struct GeneratedLayoutBuilder {
+    layout: GeneratedLayout,
+}
+
+impl GeneratedLayoutBuilder {
+    #[must_use]
+    pub fn push(mut self, view: Rectangle) -> Self {
+        self.layout.views.push(view);
+        self
+    }
+
+    /// Push all views of an iterator.
+    #[must_use]
+    pub fn extend(mut self, views: impl IntoIterator<Item = Rectangle>) -> Self {
+        self.layout.views.extend(views);
+        self
+    }
+
+    /// Push a view, shrinking it by `gap` pixels on all sides. See [`Rectangle::shrink`], and
+    /// [`SmartGaps`] for skipping the gap with few views.
+    #[must_use]
+    pub fn push_with_gap(self, view: Rectangle, gap: u32) -> Self {
+        self.push(view.shrink(gap))
+    }
+
+    /// Replace the name passed to [`GeneratedLayout::builder`], for names which depend on the
+    /// views:
+    ///
+    /// ```
+    /// use river_layout_toolkit::{GeneratedLayout, Rectangle};
+    ///
+    /// let mut builder = GeneratedLayout::builder("");
+    /// for x in [0, 100] {
+    ///     builder = builder.push(Rectangle::new(x, 0, 100, 100));
+    /// }
+    /// let layout = builder.name("[2] []=").build();
+    /// assert_eq!(layout.layout_name, "[2] []=");
+    /// ```
+    #[must_use]
+    pub fn name(mut self, layout_name: impl Into<String>) -> Self {
+        self.layout.set_name(layout_name);
+        self
+    }
+
+    pub fn build(self) -> GeneratedLayout {
+        self.layout
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error<E: StdError> {
+    #[error("Could not connect to Waylasd: {0}")]
+    WaylandConnect(#[from] wayrs_client::ConnectError),
+    #[error("Unsupported compositor: {0}")]
+    WaylandBind(#[from] wayrs_client::global::BindError),
+    /// The compositor does not advertise `river_layout_manager_v3`. By default, it has to be
+    /// advertised on startup, see [`RunOptions::wait_for_layout_manager`].
+    #[error(
+        "river_layout_manager_v3 global not found ({0}); is this river 0.2 or newer, \
+         and is WAYLAND_DISPLAY pointing at it?"
+    )]
+    LayoutManagerNotFound(wayrs_client::global::BindError),
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    /// Communication with the compositor failed, usually because it closed the connection.
+    #[error("Wayland connection failed while {phase}: {source}")]
+    Connection {
+        phase: IoPhase,
+        #[source]
+        source: io::Error,
+    },
+    /// Another layout generator uses the namespace. The compositor reports this per layout object,
+    /// `output` is the output of the one that was rejected.
+    #[error("Namespace '{namespace}' is in use (rejected on output '{output}')")]
+    NamespaceInUse { namespace: String, output: String },
+    #[error("Invalid generated layout on output '{output}': expected {expected} views, got {got}")]
+    InvalidGeneratedLayout {
+        expected: u32,
+        got: usize,
+        output: String,
+    },
+    /// A layout [streamed](Layout::stream_layout) a different number of views than demanded.
+    /// This is the equivalent of [`Error::InvalidGeneratedLayout`], checked once the layout has
+    /// finished.
+    #[error(
+        "Streamed layout on output '{output}' has the wrong view count: expected {expected}, \
+         pushed {pushed}"
+    )]
+    ViewCountMismatch {
+        expected: u32,
+        pushed: usize,
+        output: String,
+    },
+    /// A layout returned `None` from [`Layout::stream_layout`] after pushing views, which have
+    /// already been sent. The demand is committed without a name.
+    #[error("Layout on output '{output}' pushed {pushed} views and then declined to stream")]
+    StreamAbandoned { pushed: usize, output: String },
+    /// Returned only if [`RunOptions::detect_overlap`] is enabled.
+    #[error("Overlapping views on output '{output}': {overlaps:?}")]
+    OverlappingViews {
+        /// Pairs of indices of overlapping views.
+        overlaps: Vec<(usize, usize)>,
+        output: String,
+    },
+    /// Returned only if [`RunOptions::on_zero_size_view`] is [`ErrorPolicy::Error`].
+    #[error("View {index} on output '{output}' has a zero width or height")]
+    ZeroSizeView { index: usize, output: String },
+    #[error("Namespace {0:?} contains a null byte")]
+    InvalidNamespace(String),
+    /// Several layouts passed to [`run_many`] have the same namespace.
+    #[error("Namespace {0:?} is used by several layouts")]
+    DuplicateNamespace(String),
+    #[error("Layout name {0:?} contains a null byte")]
+    InvalidLayoutName(String),
+    #[error("Layout error: {0}")]
+    LayoutError(E),
+}
+
+impl<E: StdError> Error<E> {
+    /// Whether the error means that the connection to the compositor is lost or could not be
+    /// established.
+    fn is_connection_error(&self) -> bool {
+        matches!(self, Self::WaylandConnect(_) | Self::Connection { .. })
+    }
+
+    /// Wrap an error of the given phase in [`Error::Connection`].
+    fn connection(phase: IoPhase) -> impl FnOnce(io::Error) -> Self {
+        move |source| Self::Connection { phase, source }
+    }
+}
+
+/// What the layout generator was doing when [`Error::Connection`] occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoPhase {
+    /// Waiting for the initial state of the compositor.
+    Roundtrip,
+    /// Sending requests.
+    Flush,
+    /// Receiving events.
+    Recv,
+}
+
+impl fmt::Display for IoPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Roundtrip => "waiting for the initial roundtrip",
+            Self::Flush => "sending requests",
+            Self::Recv => "receiving events",
+        })
+    }
+}
+
+pub fn run<L: Layout>(layout: L) -> Result<(), Error<L::Error>> {
+    LayoutRunner::builder().run(layout)
+}
+
+/// Run a layout generator made of a single closure, see [`FnLayout`]. All commands are ignored.
+///
+/// ```no_run
+/// use river_layout_toolkit::{layouts::Monocle, run_fn};
+///
+/// run_fn("monocle", |ctx| Ok::<_, std::convert::Infallible>(Monocle.layout(ctx))).unwrap();
+/// ```
+pub fn run_fn<G, E>(namespace: &str, generate: G) -> Result<(), Error<E>>
+where
+    G: FnMut(&LayoutContext<'_>) -> Result<GeneratedLayout, E> + 'static,
+    E: StdError + 'static,
+{
+    run(FnLayout::new(namespace, generate))
+}
+
+/// Same as [`run`], but with non-default [`RunOptions`].
+pub fn run_with_options<L: Layout>(layout: L, options: RunOptions) -> Result<(), Error<L::Error>> {
+    LayoutRunner::builder().options(options).run(layout)
+}
+
+/// Run several layouts on one connection, each under its own namespace, instead of running a
+/// process for each of them. Layouts of different types can be run together as [`BoxedLayout`]s:
+///
+/// ```no_run
+/// use river_layout_toolkit::layouts::{MasterStack, Monocle};
+/// use river_layout_toolkit::{run_many, FnLayout, Layout};
+/// use std::convert::Infallible;
+///
+/// run_many(vec![
+///     FnLayout::new("monocle", |ctx| Ok::<_, Infallible>(Monocle.layout(ctx))).boxed(),
+///     FnLayout::new("master-stack", |ctx| {
+///         Ok::<_, Infallible>(MasterStack::default().layout(ctx))
+///     })
+///     .boxed(),
+/// ])
+/// .unwrap();
+/// ```
+///
+/// Every output gets a layout object for each layout, and river asks the one selected with
+/// `riverctl default-layout` or `riverctl output-layout` for layouts. Commands sent with
+/// `riverctl send-layout-cmd <namespace>` go to the layout with that namespace.
+///
+/// # Panics
+///
+/// Panics if `layouts` is empty.
+pub fn run_many<L: Layout>(layouts: Vec<L>) -> Result<(), Error<L::Error>> {
+    LayoutRunner::builder().run_many(layouts)
+}
+
+/// Options which control how the layout generator is run.
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// What to do when a layout generates a different number of views than demanded.
+    pub on_view_count_mismatch: Policy,
+    /// When the number of views of a [streamed](Layout::stream_layout) layout is checked.
+    /// Layouts returned from [`Layout::generate_layout`] are always checked as a whole.
+    pub view_count_check: ViewCountCheck,
+    /// What to do when [`Layout::generate_layout`] returns an error. Either way, the demand is
+    /// answered with the previous layout of the output if it has the right number of views, or
+    /// without any views, which river rejects, keeping the current arrangement of the windows.
+    pub on_generate_error: ErrorPolicy,
+    /// Stop with [`Error::LayoutError`] when [`Layout::user_cmd`] returns an error, instead of
+    /// only logging it. Errors of commands from the control socket, available with the
+    /// `control-socket` feature, are sent back to the client either way. Disabled by default.
+    pub fatal_user_cmd_errors: bool,
+    /// Snap every view to whole device pixels of an output with this fractional scale, see
+    /// [`Rectangle::snap_to_scale`]. Edges on the border of the usable area are kept in place, so
+    /// views which cover the usable area still cover it.
+    ///
+    /// The scale has to be given here because outputs only report integer scales, with which
+    /// snapping has no effect. `None`, the default, disables snapping.
+    pub snap_to_scale: Option<f64>,
+    /// Clamp every view to the usable area before sending it to the compositor, logging a warning
+    /// for each view which had to be changed. Disabled by default.
+    pub clamp_to_usable: bool,
+    /// Log a warning when answering a layout demand takes longer than this, since river waits for
+    /// the answer before arranging the windows. One frame, `Duration::from_millis(16)` at 60 Hz,
+    /// is a good start. `None`, the default, disables the warning.
+    pub warn_slow_demands: Option<Duration>,
+    /// Fail with [`Error::OverlappingViews`] if any two views overlap. This is quadratic in the
+    /// number of views, so it is disabled by default.
+    pub detect_overlap: bool,
+    /// What to do with views which have a zero width or height, which is almost always a bug.
+    /// Views are checked as generated, before [`snap_to_scale`](Self::snap_to_scale) and
+    /// [`clamp_to_usable`](Self::clamp_to_usable). `None`, the default, sends them to the
+    /// compositor without checking.
+    pub on_zero_size_view: Option<ErrorPolicy>,
+    /// The directory passed to [`Layout::set_state_path`]. Defaults to [`default_state_dir`].
+    pub state_dir: Option<PathBuf>,
+    /// Reconnect when the connection to the compositor is lost, e.g. because river restarted,
+    /// instead of returning an error from [`LayoutRunner::run`]. [`Layout::output_removed`] is
+    /// called for every output of the old connection, and [`Layout::on_start`] once the new
+    /// connection is established. Disabled by default.
+    pub auto_reconnect: bool,
+    /// Print the generated layouts to stdout instead of applying them. Demands are still answered,
+    /// but without any views, which river rejects, so the windows are not moved. Disabled by
+    /// default.
+    pub dry_run: bool,
+    /// The number of consecutive failed reconnection attempts after which the last error is
+    /// returned. `None`, the default, means no limit.
+    pub max_reconnect_attempts: Option<u32>,
+    /// How [`LayoutRunner::run`] reads from and writes to the Wayland socket. With
+    /// [`IoMode::NonBlocking`], the runner never blocks on the socket itself, but waits for it to
+    /// become readable or writable with `poll`. `None`, the default, means [`IoMode::Blocking`].
+    ///
+    /// The result is the same either way, so this mostly matters for connections shared with
+    /// other code, see [`LayoutRunner::connection`]. [`LayoutRunner::step`] takes the mode as an
+    /// argument instead.
+    pub io_mode: Option<IoMode>,
+    /// Wait for the compositor to advertise `river_layout_manager_v3` if it is not available on
+    /// startup, instead of failing with [`Error::LayoutManagerNotFound`]. No layouts are generated
+    /// until then. Disabled by default, since river advertises it right away.
+    pub wait_for_layout_manager: bool,
+    /// Listen for commands on a Unix socket at this path, relative to `$XDG_RUNTIME_DIR` unless
+    /// it is absolute. Every line sent to the socket is passed to [`Layout::user_cmd`] like a
+    /// command sent with `riverctl send-layout-cmd`, with `tags` set to `None`, and answered with
+    /// `ok` or `error: <message>`. Clients may stay connected to send more commands, but lines are
+    /// limited to 4096 bytes. For example:
+    ///
+    /// ```sh
+    /// echo "main-count +1" | socat - UNIX-CONNECT:$XDG_RUNTIME_DIR/my-layout.sock
+    /// ```
+    ///
+    /// With [`run_many`], commands go to the first layout, unless they are prefixed with
+    /// `@<namespace> `.
+    ///
+    /// The protocol does not allow requesting a new layout, so changes only become visible with
+    /// the next layout demand. `None`, the default, disables the socket.
+    #[cfg(feature = "control-socket")]
+    pub control_socket: Option<PathBuf>,
+    /// The output whose name is passed to [`Layout::user_cmd`] for commands from the control
+    /// socket. `None`, the default, passes each command once for every output.
+    #[cfg(feature = "control-socket")]
+    pub control_output: Option<String>,
+    /// The outputs to generate layouts for, by name. Excluded outputs get no layout object, so
+    /// river keeps arranging their windows with its default layout. `None`, the default, manages
+    /// every output.
+    pub outputs: Option<OutputFilter>,
+}
+
+/// A filter for output names, see [`RunOptions::outputs`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputFilter {
+    /// Manage only the outputs with these names.
+    Allow(Vec<String>),
+    /// Manage all outputs except the ones with these names.
+    Deny(Vec<String>),
+}
+
+impl OutputFilter {
+    /// Whether the output with the given name is managed.
+    pub fn allows(&self, output: &str) -> bool {
+        match self {
+            Self::Allow(names) => names.iter().any(|name| name == output),
+            Self::Deny(names) => !names.iter().any(|name| name == output),
+        }
+    }
+}
+
+/// How to handle a problem with a generated layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Stop with an error.
+    #[default]
+    Error,
+    /// Log a warning and keep running.
+    Warn,
+}
+
+/// How to handle a layout which generated a different number of views than demanded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Policy {
+    /// Stop with [`Error::InvalidGeneratedLayout`], or [`Error::ViewCountMismatch`] for
+    /// [streamed](Layout::stream_layout) layouts.
+    #[default]
+    Error,
+    /// Silently push only the first `view_count` views. Generating too few views is still an
+    /// error.
+    Truncate,
+    /// Log a warning and push as many views as possible. River rejects the layout if there are
+    /// too few of them, but the layout generator keeps running.
+    Warn,
+}
+
+/// When the number of views of a [streamed](Layout::stream_layout) layout is checked, see
+/// [`RunOptions::view_count_check`]. Either way, the check is handled according to
+/// [`RunOptions::on_view_count_mismatch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ViewCountCheck {
+    /// Check once [`Layout::stream_layout`] returns. Views beyond the demanded count are counted
+    /// but never sent.
+    #[default]
+    OnFinish,
+    /// End the stream as soon as one view too many is pushed, so that layouts with many views
+    /// can stop early, see [`ViewStream::is_finished`]. The error then reports one extra view,
+    /// however many the layout would have pushed.
+    OnPush,
+}
+
+/// Async version of [`run`], which waits for events without blocking the thread.
+///
+/// The [`Layout`] functions are still called synchronously. This requires a Tokio runtime with IO
+/// enabled.
+#[cfg(feature = "tokio")]
+pub async fn run_async<L: Layout>(layout: L) -> Result<(), Error<L::Error>> {
+    LayoutRunner::new_async(
+        Connection::<()>::connect()?,
+        vec![layout],
+        RunOptions::default(),
+    )
+    .await?
+    .run_async()
+    .await
+}
+
+/// Same as [`run`], but use an existing connection instead of connecting to the default display.
+///
+/// **All callbacks previously registered on `conn` are dropped**, see
+/// [`LayoutRunnerBuilder::connection`], which also allows setting [`RunOptions`].
+pub fn run_with_connection<L: Layout, D>(
+    conn: Connection<D>,
+    layout: L,
+) -> Result<(), Error<L::Error>> {
+    LayoutRunner::builder().connection(conn).run(layout)
+}
+
+/// A layout generator connected to the compositor. Use [`LayoutRunner::builder`] to create one.
+///
+/// The runner can be moved to another thread if the layout and its error are `Send`, and
+/// [`Error`] is `Send` and `Sync` if the error of the layout is, so that it can be sent back.
+/// Layouts holding closures, like [`FnLayout`], [`LayoutSet`] and [`BoxedLayout`], are not
+/// `Send`: create them on the thread which runs them, see [`stop_handle`](Self::stop_handle).
+pub struct LayoutRunner<L: Layout> {
+    conn: Connection<RunnerState<L>>,
+    state: RunnerState<L>,
+    /// The socket to reconnect to, `None` for the default one.
+    socket: Option<PathBuf>,
+    #[cfg(feature = "signals")]
+    signals: Option<signals::SignalPipe>,
+    #[cfg(feature = "control-socket")]
+    control: Option<control::ControlSocket>,
+    stop: Option<StopHandle>,
+    /// Whether [`Layout::on_shutdown`] has been called.
+    shut_down: bool,
+}
+
+impl<L: Layout> LayoutRunner<L> {
+    pub fn builder() -> LayoutRunnerBuilder<L> {
+        LayoutRunnerBuilder {
+            socket: None,
+            conn: None,
+            options: RunOptions::default(),
+            #[cfg(feature = "signals")]
+            handle_signals: false,
+            _layout: PhantomData,
+        }
+    }
+
+    fn new<D>(
+        conn: Connection<D>,
+        layouts: Vec<L>,
+        options: RunOptions,
+    ) -> Result<Self, Error<L::Error>> {
+        #[allow(deprecated)]
+        let mut conn = conn.clear_callbacks::<RunnerState<L>>();
+        conn.blocking_roundtrip()
+            .map_err(Error::connection(IoPhase::Roundtrip))?;
+        let mut this = Self::init(conn, layouts, options)?;
+        this.conn
+            .flush(IoMode::Blocking)
+            .map_err(Error::connection(IoPhase::Flush))?;
+        Ok(this)
+    }
+
+    #[cfg(feature = "tokio")]
+    async fn new_async<D>(
+        conn: Connection<D>,
+        layouts: Vec<L>,
+        options: RunOptions,
+    ) -> Result<Self, Error<L::Error>> {
+        #[allow(deprecated)]
+        let mut conn = conn.clear_callbacks::<RunnerState<L>>();
+        conn.async_roundtrip()
+            .await
+            .map_err(Error::connection(IoPhase::Roundtrip))?;
+        let mut this = Self::init(conn, layouts, options)?;
+        this.conn
+            .async_flush()
+            .await
+            .map_err(Error::connection(IoPhase::Flush))?;
+        Ok(this)
+    }
+
+    /// Set up the state on a roundtripped connection.
+    fn init(
+        mut conn: Connection<RunnerState<L>>,
+        layouts: Vec<L>,
+        options: RunOptions,
+    ) -> Result<Self, Error<L::Error>> {
+        assert!(!layouts.is_empty(), "no layouts to run");
+
+        let state_dir = options.state_dir.clone().or_else(default_state_dir);
+        let mut slots: Vec<Slot<L>> = Vec::with_capacity(layouts.len());
+        for mut layout in layouts {
+            let namespace = CString::new(layout.namespace())
+                .map_err(|_| Error::InvalidNamespace(layout.namespace().into()))?;
+            if slots.iter().any(|slot| slot.namespace == namespace) {
+                return Err(Error::DuplicateNamespace(layout.namespace().into()));
+            }
+            if let Some(dir) = &state_dir {
+                let path = dir.join(format!("{}.json", layout.namespace()));
+                layout.set_state_path(&path);
+            }
+            slots.push(Slot { namespace, layout });
+        }
+        conn.add_registry_cb(wl_registry_cb);
+
+        #[cfg(feature = "control-socket")]
+        let control = match &options.control_socket {
+            Some(path) => {
+                let path = runtime_path(path).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, "XDG_RUNTIME_DIR is not set")
+                })?;
+                let control = control::ControlSocket::bind(path)?;
+                log::info!("listening for commands on {}", control.path().display());
+                Some(control)
+            }
+            None => None,
+        };
+
+        let layout_manager = bind_layout_manager(&mut conn, options.wait_for_layout_manager)?;
+        for slot in &mut slots {
+            slot.layout.on_start().map_err(Error::LayoutError)?;
+        }
+        let mut state = RunnerState {
+            layout_manager,
+            protocol_version: layout_manager.map_or(0, |m| m.version()),
+            layouts: slots,
+            options,
+            outputs: HashMap::new(),
+            output_reg_names: HashMap::new(),
+            layout_objects: HashMap::new(),
+            views_buf: Vec::new(),
+            layout_name_buf: String::new(),
+            error: None,
+        };
+
+        state.bind_outputs(&mut conn)?;
+
+        Ok(Self {
+            conn,
+            state,
+            socket: None,
+            #[cfg(feature = "signals")]
+            signals: None,
+            #[cfg(feature = "control-socket")]
+            control,
+            stop: None,
+            shut_down: false,
+        })
+    }
+
+    /// Run the layout generator until an error occurs or it is asked to shut down.
+    ///
+    /// With [`RunOptions::auto_reconnect`], a lost connection is reestablished instead of
+    /// returning an error.
+    pub fn run(mut self) -> Result<(), Error<L::Error>> {
+        loop {
+            match self.run_connected() {
+                Err(err) if self.state.options.auto_reconnect && err.is_connection_error() => {
+                    if !self.reconnect_with_backoff(err)? {
+                        self.notify_shutdown();
+                        return Ok(());
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn run_connected(&mut self) -> Result<(), Error<L::Error>> {
+        let mode = self.state.options.io_mode.unwrap_or(IoMode::Blocking);
+        let mut fds = poll::PollSet::default();
+        loop {
+            self.dispatch()?;
+            // With non-blocking IO, the rest is sent once the socket becomes writable.
+            let flush_pending = match self.conn.flush(mode) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => true,
+                result => {
+                    result.map_err(Error::connection(IoPhase::Flush))?;
+                    false
+                }
+            };
 
-    /// This function is called whenever the user sends a command via `riverctl send-layout-cmd`.
+            // Negative fds are ignored by `poll`.
+            let signal_fd = self.signal_fd();
+            #[cfg(feature = "control-socket")]
+            let control_fd = self.control.as_ref().map_or(-1, AsRawFd::as_raw_fd);
+            #[cfg(not(feature = "control-socket"))]
+            let control_fd = -1;
+            let stop_fd = self.stop.as_ref().map_or(-1, StopHandle::fd);
+
+            if mode == IoMode::NonBlocking || signal_fd >= 0 || control_fd >= 0 || stop_fd >= 0 {
+                fds.clear();
+                let conn_idx = fds.push(self.conn.as_raw_fd(), flush_pending);
+                let signal_idx = fds.push(signal_fd, false);
+                let stop_idx = fds.push(stop_fd, false);
+                // The control socket comes last, followed by its clients.
+                #[cfg(feature = "control-socket")]
+                let control_idx = fds.push(control_fd, false);
+                #[cfg(feature = "control-socket")]
+                if let Some(control) = &self.control {
+                    for fd in control.client_fds() {
+                        fds.push(fd, false);
+                    }
+                }
+                fds.poll(None)?;
+                if fds.is_ready(signal_idx) || fds.is_ready(stop_idx) {
+                    return self.shutdown();
+                }
+                #[cfg(feature = "control-socket")]
+                if fds.any_ready_from(control_idx) {
+                    self.process_control()?;
+                }
+                if fds.is_ready(conn_idx) {
+                    match self.conn.recv_events(IoMode::NonBlocking) {
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => (),
+                        result => result.map_err(Error::connection(IoPhase::Recv))?,
+                    }
+                }
+                continue;
+            }
+
+            self.conn
+                .recv_events(IoMode::Blocking)
+                .map_err(Error::connection(IoPhase::Recv))?;
+        }
+    }
+
+    /// Forget all outputs of the lost connection, then try to connect again, waiting longer after
+    /// every failed attempt. Returns `false` if the runner was stopped or received a signal in the
+    /// meantime.
+    fn reconnect_with_backoff(
+        &mut self,
+        mut err: Error<L::Error>,
+    ) -> Result<bool, Error<L::Error>> {
+        log::warn!("connection lost: {err}");
+        for (_, output) in self.state.outputs.drain() {
+            if !output.river_layouts.is_empty() {
+                for slot in &mut self.state.layouts {
+                    slot.layout.output_removed(&output.info.name);
+                }
+            }
+        }
+        self.state.output_reg_names.clear();
+        self.state.layout_objects.clear();
+
+        let mut delay = RECONNECT_INITIAL_DELAY;
+        let mut attempt = 0;
+        loop {
+            if let Some(max) = self.state.options.max_reconnect_attempts {
+                if attempt >= max {
+                    return Err(err);
+                }
+            }
+            attempt += 1;
+            // Sleep, but keep reacting to signals and to the stop handle.
+            let stop_fd = self.stop.as_ref().map_or(-1, StopHandle::fd);
+            if poll::poll_readable([self.signal_fd(), stop_fd], Some(delay))?.contains(&true) {
+                return Ok(false);
+            }
+            delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+            match self.reconnect() {
+                Ok(()) => {
+                    log::info!("reconnected after {attempt} attempt(s)");
+                    return Ok(true);
+                }
+                // The compositor may still be starting up and not have advertised its globals.
+                Err(e)
+                    if e.is_connection_error() || matches!(e, Error::LayoutManagerNotFound(_)) =>
+                {
+                    log::warn!("reconnection attempt {attempt} failed: {e}");
+                    err = e;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// The fd which becomes readable once a signal is received, or `-1`.
+    fn signal_fd(&self) -> RawFd {
+        #[cfg(feature = "signals")]
+        return self.signals.as_ref().map_or(-1, AsRawFd::as_raw_fd);
+        #[cfg(not(feature = "signals"))]
+        return -1;
+    }
+
+    fn reconnect(&mut self) -> Result<(), Error<L::Error>> {
+        let conn = match &self.socket {
+            Some(path) => connect_to_socket(path)?,
+            None => Connection::<()>::connect()?,
+        };
+        #[allow(deprecated)]
+        let mut conn = conn.clear_callbacks::<RunnerState<L>>();
+        conn.blocking_roundtrip()
+            .map_err(Error::connection(IoPhase::Roundtrip))?;
+        conn.add_registry_cb(wl_registry_cb);
+        let wait = self.state.options.wait_for_layout_manager;
+        self.state.layout_manager = bind_layout_manager(&mut conn, wait)?;
+        self.state.protocol_version = self.state.layout_manager.map_or(0, |m| m.version());
+        self.state.bind_outputs(&mut conn)?;
+        conn.flush(IoMode::Blocking)
+            .map_err(Error::connection(IoPhase::Flush))?;
+        self.conn = conn;
+        for slot in &mut self.state.layouts {
+            slot.layout.on_start().map_err(Error::LayoutError)?;
+        }
+        Ok(())
+    }
+
+    /// The underlying Wayland connection, for using other protocols alongside the layout
+    /// protocol, e.g. to bind `zriver_control_v1` and send commands to river.
     ///
-    /// # Errors
+    /// The connection is shared with the runner, so the following rules apply:
     ///
-    /// An error returned from this function will be logged, but it will not terminate the application.
-    fn user_cmd(&mut self, cmd: String, tags: Option<u32>, output: &str)
-        -> Result<(), Self::Error>;
+    /// - Only create, use and destroy your own objects. Objects created by the runner, such as
+    ///   outputs, must not be destroyed.
+    /// - Do not dispatch events or clear the callbacks: events are dispatched by the
+    ///   runner, which calls the callbacks of your objects with a [`RunnerState`], giving access to
+    ///   the layout.
+    /// - Requests are sent the next time the runner flushes the connection. Flush it yourself if
+    ///   they cannot wait until the next event.
+    pub fn connection(&mut self) -> &mut Connection<RunnerState<L>> {
+        &mut self.conn
+    }
 
-    /// This function is called whenever compositor requests a layout.
+    /// A handle for stopping [`run`](Self::run) from another thread. All calls return handles to
+    /// the same runner.
     ///
-    /// # Errors
+    /// ```no_run
+    /// use river_layout_toolkit::layouts::Monocle;
+    /// use river_layout_toolkit::{FnLayout, LayoutRunner};
+    /// use std::convert::Infallible;
+    /// use std::sync::mpsc;
     ///
-    /// Returning an error from this fuction will cause [`run`] to terminate.
-    fn generate_layout(
-        &mut self,
-        view_count: u32,
-        usable_width: u32,
-        usable_height: u32,
-        tags: u32,
-        output: &str,
-    ) -> Result<GeneratedLayout, Self::Error>;
+    /// let (sender, receiver) = mpsc::channel();
+    /// let thread = std::thread::spawn(move || {
+    ///     let layout = FnLayout::new("monocle", |ctx| Ok::<_, Infallible>(Monocle.layout(ctx)));
+    ///     let mut runner = LayoutRunner::builder().build(layout)?;
+    ///     sender.send(runner.stop_handle()?).unwrap();
+    ///     runner.run()
+    /// });
+    ///
+    /// if let Ok(stop) = receiver.recv() {
+    ///     // ...
+    ///     stop.stop();
+    /// }
+    /// thread.join().unwrap().unwrap();
+    /// ```
+    ///
+    /// Like signal handling, this only applies to [`run`](Self::run).
+    pub fn stop_handle(&mut self) -> io::Result<StopHandle> {
+        match &self.stop {
+            Some(stop) => Ok(stop.clone()),
+            None => {
+                let stop = StopHandle::new()?;
+                self.stop = Some(stop.clone());
+                Ok(stop)
+            }
+        }
+    }
+
+    /// The version of the `river_layout_manager_v3` protocol negotiated with the compositor, `1`
+    /// or `2`. It is `0` while waiting for the compositor to advertise the protocol, see
+    /// [`RunOptions::wait_for_layout_manager`].
+    ///
+    /// The only difference is that version 1 does not tell which tags were focused when a command
+    /// was sent, so [`Layout::user_cmd`] always receives `None` as `tags`. The tags of layout
+    /// demands are available with both versions.
+    pub fn protocol_version(&self) -> u32 {
+        self.state.protocol_version
+    }
+
+    /// Async version of [`run`](Self::run). Signal handling configured with the builder does not
+    /// apply here.
+    #[cfg(feature = "tokio")]
+    pub async fn run_async(mut self) -> Result<(), Error<L::Error>> {
+        loop {
+            self.dispatch()?;
+            self.conn
+                .async_flush()
+                .await
+                .map_err(Error::connection(IoPhase::Flush))?;
+            self.conn
+                .async_recv_events()
+                .await
+                .map_err(Error::connection(IoPhase::Recv))?;
+        }
+    }
+
+    /// Receive events from the compositor, handle them and send the responses.
+    ///
+    /// This is an alternative to [`run`](Self::run) for integrating the layout generator into a
+    /// custom event loop. Call this function once after creating the runner, since some events
+    /// may already be queued, and then every time the file descriptor of the runner becomes
+    /// readable. With [`IoMode::NonBlocking`], this function never blocks.
+    ///
+    /// Signal handling configured with the builder only applies to [`run`](Self::run). Commands
+    /// from the control socket, available with the `control-socket` feature, are handled by every
+    /// call, so also call this function when `control_socket_fd` becomes readable. Commands which
+    /// a client sends after the call that accepted its connection are handled by later calls.
+    pub fn step(&mut self, mode: IoMode) -> Result<(), Error<L::Error>> {
+        #[cfg(feature = "control-socket")]
+        self.process_control()?;
+        match self.conn.recv_events(mode) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => (),
+            result => result.map_err(Error::connection(IoPhase::Recv))?,
+        }
+        self.dispatch()?;
+        match self.conn.flush(mode) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => (),
+            result => result.map_err(Error::connection(IoPhase::Flush))?,
+        }
+        Ok(())
+    }
+
+    /// The file descriptor of the [control socket](RunOptions::control_socket), if enabled.
+    #[cfg(feature = "control-socket")]
+    pub fn control_socket_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.control.as_ref().map(|control| {
+            // SAFETY: the socket lives as long as `self`.
+            unsafe { BorrowedFd::borrow_raw(control.as_raw_fd()) }
+        })
+    }
+
+    /// Accept the clients waiting on the control socket and handle the commands they have sent so
+    /// far, without blocking.
+    #[cfg(feature = "control-socket")]
+    fn process_control(&mut self) -> Result<(), Error<L::Error>> {
+        let Some(control) = &mut self.control else {
+            return Ok(());
+        };
+        let state = &mut self.state;
+        control.process(|cmd| state.control_cmd(cmd))?;
+        Ok(())
+    }
+
+    fn dispatch(&mut self) -> Result<(), Error<L::Error>> {
+        self.conn.dispatch_events(&mut self.state);
+        match self.state.error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Destroy all protocol objects and disconnect.
+    fn shutdown(&mut self) -> Result<(), Error<L::Error>> {
+        self.notify_shutdown();
+        for (_, output) in self.state.outputs.drain() {
+            output.drop(&mut self.conn);
+        }
+        if let Some(layout_manager) = self.state.layout_manager {
+            layout_manager.destroy(&mut self.conn);
+        }
+        self.conn
+            .flush(IoMode::Blocking)
+            .map_err(Error::connection(IoPhase::Flush))?;
+        Ok(())
+    }
+
+    /// Call [`Layout::on_shutdown`], unless it already was.
+    fn notify_shutdown(&mut self) {
+        if !mem::replace(&mut self.shut_down, true) {
+            for slot in &mut self.state.layouts {
+                slot.layout.on_shutdown();
+            }
+        }
+    }
 }
 
-#[derive(Debug)]
-pub struct GeneratedLayout {
-    pub layout_name: String,
-    pub views: Vec<Rectangle>,
+/// Lets the layouts persist their state on every exit path, see [`Layout::on_shutdown`].
+impl<L: Layout> Drop for LayoutRunner<L> {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            self.notify_shutdown();
+        }
+    }
 }
 
-#[derive(Debug)]
-pub struct Rectangle {
-    pub x: i32,
-    pub y: i32,
-    pub width: u32,
-    pub height: u32,
+/// The file descriptor of the Wayland connection. When it becomes readable, call
+/// [`LayoutRunner::step`]:
+///
+/// ```no_run
+/// use river_layout_toolkit::{Layout, LayoutRunner};
+/// use std::os::fd::AsRawFd;
+/// use wayrs_client::IoMode;
+///
+/// fn custom_loop<L: Layout>(mut runner: LayoutRunner<L>) {
+///     runner.step(IoMode::NonBlocking).unwrap();
+///     let mut fds = [libc::pollfd {
+///         fd: runner.as_raw_fd(),
+///         events: libc::POLLIN,
+///         revents: 0,
+///     }];
+///     loop {
+///         unsafe { libc::poll(fds.as_mut_ptr(), 1, -1) };
+///         runner.step(IoMode::NonBlocking).unwrap();
+///     }
+/// }
+/// ```
+impl<L: Layout> AsRawFd for LayoutRunner<L> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.conn.as_raw_fd()
+    }
 }
 
-#[derive(Debug, thiserror::Error)]
-pub enum Error<E: StdError> {
-    #[error("Could not connect to Waylasd: {0}")]
-    WaylandConnect(#[from] wayrs_client::ConnectError),
-    #[error("Unsupported compositor: {0}")]
-    WaylandBind(#[from] wayrs_client::global::BindError),
-    #[error("IO error: {0}")]
-    Io(#[from] io::Error),
-    #[error("Namespace '{0}' is in use")]
-    NamespaceInUse(String),
-    #[error("Invalid generated layout")]
-    InvalidGeneratedLayout,
-    #[error("Layout error: {0}")]
-    LayoutError(E),
+/// The file descriptor of the Wayland connection, see the [`AsRawFd`] implementation.
+impl<L: Layout> AsFd for LayoutRunner<L> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        // SAFETY: the socket is owned by `self.conn` and lives as long as `self`.
+        unsafe { BorrowedFd::borrow_raw(self.conn.as_raw_fd()) }
+    }
 }
 
-pub fn run<L: Layout>(layout: L) -> Result<(), Error<L::Error>> {
-    let mut conn = Connection::connect()?;
-    conn.blocking_roundtrip()?;
-    conn.add_registry_cb(wl_registry_cb);
-
-    let mut state = State {
-        layout_manager: conn.bind_singleton(1..=2)?,
-        last_user_cmd_tags: None,
-        layout,
-        outputs: Vec::new(),
-        error: None,
-    };
+/// A builder for [`LayoutRunner`].
+pub struct LayoutRunnerBuilder<L> {
+    socket: Option<PathBuf>,
+    conn: Option<Connection<()>>,
+    options: RunOptions,
+    #[cfg(feature = "signals")]
+    handle_signals: bool,
+    _layout: PhantomData<fn(L)>,
+}
+
+impl<L: Layout> LayoutRunnerBuilder<L> {
+    /// Connect to the given Wayland socket instead of the default one.
+    ///
+    /// Relative paths are resolved against `$XDG_RUNTIME_DIR`, so both a display name like
+    /// `wayland-1` and a full path to a socket are accepted.
+    ///
+    /// `wayrs_client` can only connect through environment variables, so connecting temporarily
+    /// sets `WAYLAND_SOCKET`. Do not read the environment from other threads while building the
+    /// runner or reconnecting, or set `WAYLAND_DISPLAY` before starting any threads instead.
+    #[must_use]
+    pub fn socket(mut self, path: impl Into<PathBuf>) -> Self {
+        self.socket = Some(path.into());
+        self
+    }
+
+    /// Use an existing connection instead of connecting to the compositor. It does not have to be
+    /// roundtripped beforehand, the runner does it.
+    ///
+    /// **All callbacks previously registered on `conn` are dropped**, because the runner
+    /// dispatches the events of the connection to its own state. Objects created before keep
+    /// working, but their events are ignored. Reconnecting with [`RunOptions::auto_reconnect`]
+    /// connects to the [`socket`](Self::socket) or the default display.
+    #[must_use]
+    pub fn connection<D>(mut self, conn: Connection<D>) -> Self {
+        #[allow(deprecated)]
+        let conn = conn.clear_callbacks();
+        self.conn = Some(conn);
+        self
+    }
 
-    loop {
-        conn.dispatch_events(&mut state);
-        if let Some(err) = state.error.take() {
-            return Err(err);
+    /// Set the [`RunOptions`].
+    #[must_use]
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Shut down gracefully on SIGINT and SIGTERM: the protocol objects are destroyed,
+    /// [`Layout::on_shutdown`] is called and [`LayoutRunner::run`] returns `Ok(())`.
+    ///
+    /// Disabled by default, because installing signal handlers affects the whole process.
+    #[cfg(feature = "signals")]
+    #[must_use]
+    pub fn handle_signals(mut self, enable: bool) -> Self {
+        self.handle_signals = enable;
+        self
+    }
+
+    /// Connect to the compositor, unless a [`connection`](Self::connection) is given.
+    pub fn build(self, layout: L) -> Result<LayoutRunner<L>, Error<L::Error>> {
+        self.build_many(vec![layout])
+    }
+
+    /// Connect to the compositor with several layouts, see [`run_many`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `layouts` is empty.
+    pub fn build_many(self, layouts: Vec<L>) -> Result<LayoutRunner<L>, Error<L::Error>> {
+        let conn = match (self.conn, &self.socket) {
+            (Some(conn), _) => conn,
+            (None, Some(path)) => connect_to_socket(path)?,
+            (None, None) => Connection::<()>::connect()?,
+        };
+        let mut runner = LayoutRunner::new(conn, layouts, self.options)?;
+        runner.socket = self.socket;
+        #[cfg(feature = "signals")]
+        if self.handle_signals {
+            runner.signals = Some(signals::SignalPipe::install()?);
         }
+        Ok(runner)
+    }
+
+    /// Connect to the compositor and run the layout generator until an error occurs.
+    pub fn run(self, layout: L) -> Result<(), Error<L::Error>> {
+        self.build(layout)?.run()
+    }
 
-        conn.flush(IoMode::Blocking)?;
-        conn.recv_events(IoMode::Blocking)?;
+    /// Connect to the compositor and run several layouts until an error occurs, see
+    /// [`run_many`].
+    pub fn run_many(self, layouts: Vec<L>) -> Result<(), Error<L::Error>> {
+        self.build_many(layouts)?.run()
     }
 }
 
-struct State<L: Layout> {
-    layout_manager: river_layout_manager_v3::RiverLayoutManagerV3,
-    last_user_cmd_tags: Option<u32>,
-    layout: L,
-    outputs: Vec<Output>,
+// Checks that running the layout generator on a spawned thread keeps working.
+const _: () = {
+    fn send_sync<T: Send + Sync>() {}
+    fn send<T: Send>() {}
+    #[allow(dead_code)]
+    fn check<E: StdError + Send + Sync, L: Layout<Error = E> + Send>() {
+        send_sync::<Error<E>>();
+        send_sync::<StopHandle>();
+        send::<LayoutRunner<L>>();
+    }
+};
+
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_millis(100);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(10);
+const LAYOUT_MANAGER_VERSIONS: std::ops::RangeInclusive<u32> = 1..=2;
+
+/// Bind the layout manager advertised so far. If there is none, it is bound in `wl_registry_cb`
+/// once it appears, but only if `wait` is set.
+fn bind_layout_manager<D, E: StdError>(
+    conn: &mut Connection<D>,
+    wait: bool,
+) -> Result<Option<RiverLayoutManagerV3>, Error<E>> {
+    match conn.bind_singleton(LAYOUT_MANAGER_VERSIONS) {
+        Ok(layout_manager) => Ok(Some(layout_manager)),
+        Err(wayrs_client::global::BindError::GlobalNotFound(_)) if wait => {
+            log::info!("waiting for the compositor to advertise river_layout_manager_v3");
+            Ok(None)
+        }
+        Err(err) => Err(Error::LayoutManagerNotFound(err)),
+    }
+}
+
+fn connect_to_socket(path: &Path) -> Result<Connection<()>, ConnectError> {
+    let path = runtime_path(path).ok_or(ConnectError::NotEnoughEnvVars)?;
+    connect_with_stream(UnixStream::connect(path)?)
+}
+
+/// Resolve a relative path against `$XDG_RUNTIME_DIR`.
+fn runtime_path(path: &Path) -> Option<PathBuf> {
+    if path.is_absolute() {
+        Some(path.to_owned())
+    } else {
+        Some(Path::new(&env::var_os("XDG_RUNTIME_DIR")?).join(path))
+    }
+}
+
+/// Create a connection on an already connected socket.
+///
+/// `wayrs_client` can only connect using environment variables, so the socket is passed via
+/// `WAYLAND_SOCKET` for the duration of the call, like a parent process would. Concurrent calls are
+/// serialized, but reading the environment from other threads at the same time, e.g. through
+/// `getenv` in C code, is a data race. This is the limitation documented on
+/// [`LayoutRunnerBuilder::socket`] and [`MockServer::start`](crate::mock::MockServer::start).
+fn connect_with_stream(stream: UnixStream) -> Result<Connection<()>, ConnectError> {
+    static WAYLAND_SOCKET: Mutex<()> = Mutex::new(());
+    let _guard = WAYLAND_SOCKET
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let fd = OwnedFd::from(stream);
+    let prev = env::var_os("WAYLAND_SOCKET");
+    env::set_var("WAYLAND_SOCKET", fd.as_raw_fd().to_string());
+    let conn = Connection::connect();
+    match prev {
+        Some(prev) => env::set_var("WAYLAND_SOCKET", prev),
+        None => env::remove_var("WAYLAND_SOCKET"),
+    }
+    if conn.is_ok() {
+        // The connection owns the socket now. Otherwise, dropping `fd` closes it.
+        let _ = fd.into_raw_fd();
+    }
+    conn
+}
+
+/// The state of a [`LayoutRunner`], passed to the callbacks registered on its
+/// [`connection`](LayoutRunner::connection).
+pub struct RunnerState<L: Layout> {
+    /// `None` while waiting for the global, see [`RunOptions::wait_for_layout_manager`].
+    layout_manager: Option<RiverLayoutManagerV3>,
+    protocol_version: u32,
+    /// Never empty. The layout objects of each output are stored in the same order.
+    layouts: Vec<Slot<L>>,
+    options: RunOptions,
+    outputs: HashMap<WlOutput, Output>,
+    output_reg_names: HashMap<u32, WlOutput>,
+    /// The output of each layout object and the index of its layout.
+    layout_objects: HashMap<RiverLayoutV3, (WlOutput, usize)>,
+    views_buf: Vec<Rectangle>,
+    layout_name_buf: String,
     error: Option<Error<L::Error>>,
 }
 
+impl<L: Layout> RunnerState<L> {
+    /// The layout, or the first one when running several, see [`layouts`](Self::layouts).
+    pub fn layout(&self) -> &L {
+        &self.layouts[0].layout
+    }
+
+    pub fn layout_mut(&mut self) -> &mut L {
+        &mut self.layouts[0].layout
+    }
+
+    /// All layouts, in the order they were passed to [`run_many`].
+    pub fn layouts(&self) -> impl Iterator<Item = &L> {
+        self.layouts.iter().map(|slot| &slot.layout)
+    }
+
+    pub fn layouts_mut(&mut self) -> impl Iterator<Item = &mut L> {
+        self.layouts.iter_mut().map(|slot| &mut slot.layout)
+    }
+
+    /// Bind all outputs advertised so far.
+    fn bind_outputs(
+        &mut self,
+        conn: &mut Connection<Self>,
+    ) -> Result<(), wayrs_client::global::BindError> {
+        // The connection may have been used before, in which case the registry events for the
+        // already advertised outputs were consumed. Outputs which are still queued are skipped in
+        // `wl_registry_cb`.
+        let output_globals: Vec<_> = conn
+            .globals()
+            .iter()
+            .filter(|g| g.is::<WlOutput>())
+            .cloned()
+            .collect();
+        for global in &output_globals {
+            self.bind_output(conn, global)?;
+        }
+        Ok(())
+    }
+
+    fn bind_output(
+        &mut self,
+        conn: &mut Connection<Self>,
+        global: &Global,
+    ) -> Result<(), wayrs_client::global::BindError> {
+        let output = Output::bind(conn, global)?;
+        let wl_output = output.wl_output;
+        self.output_reg_names.insert(output.reg_name, wl_output);
+        self.outputs.insert(wl_output, output);
+        // Version 1 does not even have the `done` event.
+        if wl_output.version() < 2 {
+            self.create_layout(conn, wl_output);
+        }
+        Ok(())
+    }
+
+    /// Create the layout objects once the output is ready, one for each layout.
+    ///
+    /// Without a layout manager, the output is only marked as ready by giving it a name, and the
+    /// layout objects are created in `bind_late_layout_manager`.
+    fn create_layout(&mut self, conn: &mut Connection<Self>, wl_output: WlOutput) {
+        let Some(output) = self.outputs.get_mut(&wl_output) else {
+            return;
+        };
+        if output.info.name.is_empty() {
+            // Output names were added in version 4.
+            output.info.name = format!("wl_output-{}", output.reg_name);
+            output.info.fill_make_model();
+        }
+        if let Some(filter) = &self.options.outputs {
+            if !filter.allows(&output.info.name) {
+                log::debug!("not managing output '{}'", output.info.name);
+                return;
+            }
+        }
+        let Some(layout_manager) = self.layout_manager else {
+            return;
+        };
+        self.warn_on_duplicate_name(wl_output);
+        let Some(output) = self.outputs.get_mut(&wl_output) else {
+            return;
+        };
+        for (index, slot) in self.layouts.iter_mut().enumerate() {
+            let river = layout_manager.get_layout_with_cb(
+                conn,
+                wl_output,
+                slot.namespace.clone(),
+                river_layout_cb,
+            );
+            self.layout_objects.insert(river, (wl_output, index));
+            output.river_layouts.push(RiverLayout {
+                river,
+                last_user_cmd_tags: None,
+                usable_area: None,
+                tags: None,
+                last_layout: None,
+            });
+            slot.layout.output_added(&output.info.name);
+        }
+    }
+
+    /// See [`RunOptions::warn_slow_demands`].
+    fn warn_if_slow(&self, wl_output: WlOutput, view_count: u32, start: Instant) {
+        let Some(threshold) = self.options.warn_slow_demands else {
+            return;
+        };
+        let elapsed = start.elapsed();
+        if elapsed > threshold {
+            let output = self
+                .outputs
+                .get(&wl_output)
+                .map_or("<unknown>", |output| output.info.name.as_str());
+            log::warn!(
+                "layout demand on output '{output}' with {view_count} views took {elapsed:?}, \
+                 more than {threshold:?}"
+            );
+        }
+    }
+
+    /// State keyed by output name is shared between outputs with the same name, which is confusing
+    /// enough to point out.
+    fn warn_on_duplicate_name(&self, wl_output: WlOutput) {
+        let Some(output) = self.outputs.get(&wl_output) else {
+            return;
+        };
+        let duplicate = self.outputs.values().find(|other| {
+            other.wl_output != wl_output
+                && !other.river_layouts.is_empty()
+                && other.info.name == output.info.name
+        });
+        if let Some(other) = duplicate {
+            log::warn!(
+                "outputs with registry names {} and {} are both named '{}', \
+                 state kept per output name is shared between them",
+                other.reg_name,
+                output.reg_name,
+                output.info.name
+            );
+        }
+    }
+
+    /// Bind a layout manager advertised after startup and create the layout objects of all outputs
+    /// which became ready in the meantime.
+    fn bind_late_layout_manager(
+        &mut self,
+        conn: &mut Connection<Self>,
+        global: &Global,
+    ) -> Result<(), wayrs_client::global::BindError> {
+        let layout_manager: RiverLayoutManagerV3 = global.bind(conn, LAYOUT_MANAGER_VERSIONS)?;
+        log::info!("river_layout_manager_v3 appeared, binding it");
+        self.layout_manager = Some(layout_manager);
+        self.protocol_version = layout_manager.version();
+        let ready: Vec<_> = self
+            .outputs
+            .values()
+            .filter(|o| o.river_layouts.is_empty() && !o.info.name.is_empty())
+            .map(|o| o.wl_output)
+            .collect();
+        for wl_output in ready {
+            self.create_layout(conn, wl_output);
+        }
+        Ok(())
+    }
+
+    /// Remember the tags of a demand, returning the ones of the previous demand.
+    fn replace_tags(&mut self, wl_output: WlOutput, slot: usize, tags: Tags) -> Option<Tags> {
+        let layout = self
+            .outputs
+            .get_mut(&wl_output)?
+            .river_layouts
+            .get_mut(slot)?;
+        layout.tags.replace(tags)
+    }
+
+    fn update_usable_area(&mut self, wl_output: WlOutput, slot: usize, new: (u32, u32)) {
+        let Some(output) = self.outputs.get_mut(&wl_output) else {
+            return;
+        };
+        let Some(layout) = output.river_layouts.get_mut(slot) else {
+            return;
+        };
+        let old = layout.usable_area.replace(new);
+        if old != Some(new) {
+            self.layouts[slot]
+                .layout
+                .usable_area_changed(&output.info.name, old, new);
+        }
+    }
+
+    /// Pass a command from the control socket to the layout.
+    #[cfg(feature = "control-socket")]
+    fn control_cmd(&mut self, cmd: &str) -> Result<(), String> {
+        let (slot, cmd) = match cmd.strip_prefix('@').and_then(|cmd| cmd.split_once(' ')) {
+            Some((namespace, cmd)) => {
+                let slot = self
+                    .layouts
+                    .iter()
+                    .position(|slot| slot.namespace.as_bytes() == namespace.as_bytes())
+                    .ok_or_else(|| format!("unknown namespace '{namespace}'"))?;
+                (slot, cmd.trim_start())
+            }
+            None => (0, cmd),
+        };
+        let outputs: Vec<String> = self
+            .outputs
+            .values()
+            .filter(|output| !output.river_layouts.is_empty())
+            .map(|output| output.info.name.clone())
+            .filter(|name| {
+                self.options
+                    .control_output
+                    .as_ref()
+                    .is_none_or(|o| o == name)
+            })
+            .collect();
+        if outputs.is_empty() {
+            return Err(match &self.options.control_output {
+                Some(name) => format!("unknown output '{name}'"),
+                None => "no outputs".to_owned(),
+            });
+        }
+        log::debug!("control command {cmd:?}");
+        for output in outputs {
+            self.layouts[slot]
+                .layout
+                .user_cmd(cmd.to_owned(), None, &output)
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn remove_output(&mut self, reg_name: u32) -> Option<Output> {
+        let output = self
+            .outputs
+            .remove(&self.output_reg_names.remove(&reg_name)?)?;
+        for river_layout in &output.river_layouts {
+            self.layout_objects.remove(&river_layout.river);
+        }
+        Some(output)
+    }
+}
+
+/// A layout and the namespace it is registered with.
+struct Slot<L> {
+    namespace: CString,
+    layout: L,
+}
+
 struct Output {
     wl_output: WlOutput,
     reg_name: u32,
-    river_layout: Option<RiverLayout>,
+    info: OutputInfo,
+    /// The properties received since the last `done` event.
+    pending: PendingOutputInfo,
+    /// One for each layout, empty until the output is ready.
+    river_layouts: Vec<RiverLayout>,
+}
+
+/// Output properties are atomic: they only take effect with the next `done` event, so that layouts
+/// never see a half-updated output.
+#[derive(Default)]
+struct PendingOutputInfo {
+    name: Option<String>,
+    make_model: Option<(String, String)>,
+    geometry: Option<OutputGeometry>,
+    mode: Option<OutputMode>,
+    scale: Option<i32>,
+    transform: Option<OutputTransform>,
+}
+
+impl PendingOutputInfo {
+    fn apply(&mut self, info: &mut OutputInfo) {
+        if let Some(name) = self.name.take() {
+            info.name = name;
+        }
+        if let Some((make, model)) = self.make_model.take() {
+            info.make = make;
+            info.model = model;
+        }
+        info.fill_make_model();
+        if let Some(geometry) = self.geometry.take() {
+            info.geometry = Some(geometry);
+        }
+        if let Some(mode) = self.mode.take() {
+            info.mode = Some(mode);
+        }
+        if let Some(scale) = self.scale.take() {
+            info.scale = scale;
+        }
+        if let Some(transform) = self.transform.take() {
+            info.transform = transform;
+        }
+    }
 }
 
 struct RiverLayout {
     river: RiverLayoutV3,
-    output_name: String,
+    last_user_cmd_tags: Option<Tags>,
+    /// The usable area of the last layout demand, see [`Layout::usable_area_changed`].
+    usable_area: Option<(u32, u32)>,
+    /// The focused tags of the last layout demand.
+    tags: Option<Tags>,
+    /// The last committed layout. Its buffers are swapped with the ones of `RunnerState` after each
+    /// commit, so keeping it does not allocate.
+    last_layout: Option<GeneratedLayout>,
 }
 
 impl Output {
-    fn bind<L: Layout>(conn: &mut Connection<State<L>>, global: &Global) -> Self {
-        Self {
-            wl_output: global.bind_with_cb(conn, 4, wl_output_cb).unwrap(),
+    fn bind<L: Layout>(
+        conn: &mut Connection<RunnerState<L>>,
+        global: &Global,
+    ) -> Result<Self, wayrs_client::global::BindError> {
+        Ok(Self {
+            wl_output: global.bind_with_cb(conn, 1..=4, wl_output_cb)?,
             reg_name: global.name,
-            river_layout: None,
-        }
+            info: OutputInfo::new(String::new()),
+            pending: PendingOutputInfo::default(),
+            river_layouts: Vec::new(),
+        })
     }
 
-    fn drop<L: Layout>(self, conn: &mut Connection<State<L>>) {
-        if let Some(river_layout) = self.river_layout {
+    fn drop<L: Layout>(self, conn: &mut Connection<RunnerState<L>>) {
+        for river_layout in self.river_layouts {
             river_layout.river.destroy(conn);
         }
-        self.wl_output.release(conn);
+        // Before version 3, outputs cannot be destroyed.
+        if self.wl_output.version() >= 3 {
+            self.wl_output.release(conn);
+        }
     }
 }
 
 fn wl_registry_cb<L: Layout>(
-    conn: &mut Connection<State<L>>,
-    state: &mut State<L>,
+    conn: &mut Connection<RunnerState<L>>,
+    state: &mut RunnerState<L>,
     event: &wl_registry::Event,
 ) {
     match event {
-        wl_registry::Event::Global(global) if global.is::<WlOutput>() => {
-            state.outputs.push(Output::bind(conn, global));
+        wl_registry::Event::Global(global)
+            if global.is::<WlOutput>() && !state.output_reg_names.contains_key(&global.name) =>
+        {
+            if let Err(err) = state.bind_output(conn, global) {
+                state.error = Some(err.into());
+                conn.break_dispatch_loop();
+            }
+        }
+        wl_registry::Event::Global(global)
+            if global.is::<RiverLayoutManagerV3>() && state.layout_manager.is_none() =>
+        {
+            if let Err(err) = state.bind_late_layout_manager(conn, global) {
+                state.error = Some(Error::LayoutManagerNotFound(err));
+                conn.break_dispatch_loop();
+            }
         }
         wl_registry::Event::GlobalRemove(name) => {
-            if let Some(output_index) = state.outputs.iter().position(|o| o.reg_name == *name) {
-                let output = state.outputs.swap_remove(output_index);
+            if let Some(output) = state.remove_output(*name) {
+                if !output.river_layouts.is_empty() {
+                    for slot in &mut state.layouts {
+                        slot.layout.output_removed(&output.info.name);
+                    }
+                }
                 output.drop(conn);
             }
         }
@@ -154,97 +2286,295 @@ fn wl_registry_cb<L: Layout>(
     }
 }
 
-fn wl_output_cb<L: Layout>(ctx: EventCtx<State<L>, WlOutput>) {
-    let output = ctx
-        .state
-        .outputs
-        .iter_mut()
-        .find(|o| o.wl_output == ctx.proxy)
-        .expect("Received event for unknown output");
-
-    if output.river_layout.is_some() {
+fn wl_output_cb<L: Layout>(ctx: EventCtx<RunnerState<L>, WlOutput>) {
+    let Some(output) = ctx.state.outputs.get_mut(&ctx.proxy) else {
+        log::warn!("Received event for unknown output");
         return;
+    };
+
+    let mut ready = false;
+    match ctx.event {
+        wl_output::Event::Geometry(args) => {
+            output.pending.geometry = Some(OutputGeometry {
+                x: args.x,
+                y: args.y,
+                physical_width: args.physical_width,
+                physical_height: args.physical_height,
+            });
+            output.pending.transform = Some(OutputTransform::from_wayland(args.transform));
+            output.pending.make_model = Some((
+                args.make.to_string_lossy().into_owned(),
+                args.model.to_string_lossy().into_owned(),
+            ));
+        }
+        wl_output::Event::Mode(args) if args.flags.contains(wl_output::Mode::Current) => {
+            output.pending.mode = Some(OutputMode {
+                width: args.width,
+                height: args.height,
+                refresh: args.refresh,
+            });
+        }
+        wl_output::Event::Scale(scale) => {
+            output.pending.scale = Some(scale);
+        }
+        // The name never changes once the layout objects exist.
+        wl_output::Event::Name(name) if output.river_layouts.is_empty() => {
+            output.pending.name = Some(name.to_string_lossy().into_owned());
+        }
+        wl_output::Event::Done => {
+            output.pending.apply(&mut output.info);
+            ready = output.river_layouts.is_empty();
+        }
+        _ => (),
     }
 
-    if let wl_output::Event::Name(name) = ctx.event {
-        output.river_layout = Some(RiverLayout {
-            river: ctx.state.layout_manager.get_layout_with_cb(
-                ctx.conn,
-                output.wl_output,
-                CString::new(L::NAMESPACE).unwrap(),
-                river_layout_cb,
-            ),
-            output_name: name.into_string().unwrap(),
-        });
+    // Version 1 does not have the `done` event, so every property is applied right away.
+    if ctx.proxy.version() < 2 {
+        output.pending.apply(&mut output.info);
+    }
+    if ready {
+        ctx.state.create_layout(ctx.conn, ctx.proxy);
     }
 }
 
-fn river_layout_cb<L: Layout>(ctx: EventCtx<State<L>, RiverLayoutV3>) {
+fn river_layout_cb<L: Layout>(ctx: EventCtx<RunnerState<L>, RiverLayoutV3>) {
     use river_layout_v3::Event;
 
-    let layout = ctx
+    let Some(&(wl_output, slot)) = ctx.state.layout_objects.get(&ctx.proxy) else {
+        log::warn!("Received event for unknown layout object");
+        return;
+    };
+    let mut previous_tags = None;
+    if let Event::LayoutDemand(args) = &ctx.event {
+        let new = (args.usable_width, args.usable_height);
+        ctx.state.update_usable_area(wl_output, slot, new);
+        previous_tags = ctx.state.replace_tags(wl_output, slot, Tags(args.tags));
+    }
+    let Some((info, layout)) = ctx
         .state
         .outputs
-        .iter()
-        .filter_map(|o| o.river_layout.as_ref())
-        .find(|o| o.river == ctx.proxy)
-        .expect("Received event for unknown layout object");
+        .get(&wl_output)
+        .and_then(|output| Some((&output.info, output.river_layouts.get(slot)?)))
+    else {
+        log::warn!("Received event for unknown layout object");
+        return;
+    };
 
     match ctx.event {
         Event::NamespaceInUse => {
-            ctx.state.error = Some(Error::NamespaceInUse(L::NAMESPACE.into()));
+            let namespace = ctx.state.layouts[slot]
+                .namespace
+                .to_string_lossy()
+                .into_owned();
+            ctx.state.error = Some(Error::NamespaceInUse {
+                namespace,
+                output: info.name.clone(),
+            });
             ctx.conn.break_dispatch_loop();
         }
         Event::LayoutDemand(args) => {
-            let generated_layout = match ctx.state.layout.generate_layout(
-                args.view_count,
-                args.usable_width,
-                args.usable_height,
-                args.tags,
-                &layout.output_name,
+            #[cfg(feature = "tracing")]
+            let _span = tracing::debug_span!(
+                "layout_demand",
+                output = %info.name,
+                view_count = args.view_count,
+                tags = args.tags,
+                serial = args.serial,
+            )
+            .entered();
+            let start = Instant::now();
+
+            let layout_ctx = LayoutContext {
+                view_count: args.view_count,
+                usable_width: args.usable_width,
+                usable_height: args.usable_height,
+                tags: Tags(args.tags),
+                output: info,
+                protocol_version: ctx.state.protocol_version,
+                serial: args.serial,
+                outputs: Outputs::Connected(&ctx.state.outputs, slot),
+                previous_layout: layout.last_layout.as_ref(),
+                previous_tags,
+            };
+            if let Some(result) = stream_demand(
+                ctx.conn,
+                ctx.proxy,
+                &mut ctx.state.layouts[slot].layout,
+                layout_ctx,
+                &ctx.state.options,
+                &mut ctx.state.views_buf,
             ) {
-                Ok(l) => l,
-                Err(e) => {
-                    ctx.state.error = Some(Error::LayoutError(e));
+                if let Some(layout) = ctx
+                    .state
+                    .outputs
+                    .get_mut(&wl_output)
+                    .and_then(|output| output.river_layouts.get_mut(slot))
+                {
+                    layout.last_layout = None;
+                }
+                if let Err(err) = result {
+                    ctx.state.error = Some(err);
                     ctx.conn.break_dispatch_loop();
-                    return;
                 }
-            };
-
-            if generated_layout.views.len() != args.view_count as usize {
-                ctx.state.error = Some(Error::InvalidGeneratedLayout);
-                ctx.conn.break_dispatch_loop();
+                #[cfg(feature = "tracing")]
+                tracing::debug!(elapsed = ?start.elapsed(), "committed streamed layout");
+                ctx.state.warn_if_slow(wl_output, args.view_count, start);
                 return;
             }
 
-            for rect in generated_layout.views {
-                layout.river.push_view_dimensions(
-                    ctx.conn,
-                    rect.x,
-                    rect.y,
-                    rect.width,
-                    rect.height,
-                    args.serial,
-                );
+            let mut demand = Demand::new(
+                layout_ctx,
+                &mut ctx.state.views_buf,
+                &mut ctx.state.layout_name_buf,
+            );
+
+            // `Err(None)` is a recoverable error, which has already been logged.
+            let result: Result<CString, Option<Error<L::Error>>> = 'demand: {
+                if let Err(e) = ctx.state.layouts[slot]
+                    .layout
+                    .generate_layout_into(&mut demand)
+                {
+                    break 'demand match ctx.state.options.on_generate_error {
+                        ErrorPolicy::Error => Err(Some(Error::LayoutError(e))),
+                        ErrorPolicy::Warn => {
+                            log::warn!("generate_layout error on output '{}': {e}", info.name);
+                            Err(None)
+                        }
+                    };
+                }
+
+                let views = &mut ctx.state.views_buf;
+                if let Err(err) = check_view_count(views, args.view_count, &info.name) {
+                    match ctx.state.options.on_view_count_mismatch {
+                        Policy::Truncate if views.len() > args.view_count as usize => {
+                            views.truncate(args.view_count as usize);
+                        }
+                        Policy::Warn => {
+                            log::warn!("{err}");
+                            views.truncate(args.view_count as usize);
+                        }
+                        _ => break 'demand Err(Some(err)),
+                    }
+                }
+
+                let usable = Rectangle::new(0, 0, args.usable_width, args.usable_height);
+                for (index, rect) in ctx.state.views_buf.iter_mut().enumerate() {
+                    let options = &ctx.state.options;
+                    if let Err(err) = adjust_view(options, &usable, index, rect, &info.name) {
+                        break 'demand Err(Some(err));
+                    }
+                }
+
+                if ctx.state.options.detect_overlap {
+                    if let Err(err) = check_overlap(&ctx.state.views_buf, &info.name) {
+                        break 'demand Err(Some(err));
+                    }
+                }
+
+                // `commit` takes an owned `CString`, so the name is copied for every demand however
+                // layouts pass it: accepting a `&CStr` or `Cow<'static, CStr>` would not avoid the
+                // copy. It is the only allocation of a demand answered with
+                // `generate_layout_into`, see `benches/demand.rs`.
+                CString::new(ctx.state.layout_name_buf.as_str())
+                    .map_err(|_| Some(Error::InvalidLayoutName(ctx.state.layout_name_buf.clone())))
+            };
+
+            match result {
+                Ok(_) if ctx.state.options.dry_run => {
+                    println!(
+                        "output '{}', serial {}: layout '{}'",
+                        info.name, args.serial, ctx.state.layout_name_buf
+                    );
+                    for (i, rect) in ctx.state.views_buf.iter().enumerate() {
+                        println!(
+                            "  {i}: {}x{} at {},{}",
+                            rect.width, rect.height, rect.x, rect.y
+                        );
+                    }
+                    ctx.proxy.commit(ctx.conn, CString::default(), args.serial);
+                }
+                Ok(layout_name) => {
+                    for (index, rect) in ctx.state.views_buf.iter().enumerate() {
+                        push_view(ctx.conn, ctx.proxy, &info.name, index, rect, args.serial);
+                    }
+                    ctx.proxy.commit(ctx.conn, layout_name, args.serial);
+
+                    if let Some(layout) = ctx
+                        .state
+                        .outputs
+                        .get_mut(&wl_output)
+                        .and_then(|output| output.river_layouts.get_mut(slot))
+                    {
+                        let last = layout.last_layout.get_or_insert_with(Default::default);
+                        mem::swap(&mut last.views, &mut ctx.state.views_buf);
+                        mem::swap(&mut last.layout_name, &mut ctx.state.layout_name_buf);
+                    }
+                }
+                Err(err) => {
+                    // Answer the demand anyway, so that river is never left waiting for it: with
+                    // the previous layout if it still fits, or without views otherwise, which
+                    // river rejects, keeping the current arrangement.
+                    match &layout.last_layout {
+                        Some(last) if last.views.len() == args.view_count as usize => {
+                            for (index, rect) in last.views.iter().enumerate() {
+                                push_view(
+                                    ctx.conn,
+                                    ctx.proxy,
+                                    &info.name,
+                                    index,
+                                    rect,
+                                    args.serial,
+                                );
+                            }
+                            let name = CString::new(last.layout_name.as_str()).unwrap_or_default();
+                            ctx.proxy.commit(ctx.conn, name, args.serial);
+                        }
+                        _ => ctx.proxy.commit(ctx.conn, CString::default(), args.serial),
+                    }
+                    if let Some(err) = err {
+                        ctx.state.error = Some(err);
+                        ctx.conn.break_dispatch_loop();
+                    }
+                }
             }
 
-            layout.river.commit(
-                ctx.conn,
-                CString::new(generated_layout.layout_name).unwrap(),
-                args.serial,
-            );
+            #[cfg(feature = "tracing")]
+            tracing::debug!(elapsed = ?start.elapsed(), "committed layout");
+            ctx.state.warn_if_slow(wl_output, args.view_count, start);
         }
         Event::UserCommand(command) => {
-            if let Err(err) = ctx.state.layout.user_cmd(
-                command.into_string().unwrap(),
-                ctx.state.last_user_cmd_tags,
-                &layout.output_name,
+            match ctx.state.layouts[slot].layout.user_cmd(
+                command.to_string_lossy().into_owned(),
+                layout.last_user_cmd_tags,
+                &info.name,
             ) {
-                log::warn!("user_cmd error: {err}");
+                Ok(UserCmdOutcome::Handled) => (),
+                Ok(UserCmdOutcome::RequestLayout) => {
+                    log::debug!(
+                        "layout on '{}' changed, expecting a layout demand",
+                        info.name
+                    );
+                }
+                Err(err) => {
+                    log::warn!("user_cmd error: {err}");
+                    if ctx.state.options.fatal_user_cmd_errors {
+                        ctx.state.error = Some(Error::LayoutError(err));
+                        ctx.conn.break_dispatch_loop();
+                    }
+                }
             }
         }
+        // Never sent with version 1, but be defensive so that `user_cmd` never sees tags then.
+        Event::UserCommandTags(_) if ctx.state.protocol_version < 2 => (),
         Event::UserCommandTags(tags) => {
-            ctx.state.last_user_cmd_tags = Some(tags);
+            if let Some(layout) = ctx
+                .state
+                .outputs
+                .get_mut(&wl_output)
+                .and_then(|output| output.river_layouts.get_mut(slot))
+            {
+                layout.last_user_cmd_tags = Some(Tags(tags));
+            }
         }
     }
 }
@@ -0,0 +1,187 @@
+//! Helpers for layout arithmetic.
+
+use crate::{FloatRect, Rectangle};
+
+/// Split `total` into `parts` sizes which add up to exactly `total`. The first `total % parts`
+/// parts are one pixel larger than the rest.
+///
+/// Yields nothing if `parts` is zero.
+pub fn distribute(total: u32, parts: u32) -> impl Iterator<Item = u32> {
+    let (size, remainder) = match parts {
+        0 => (0, 0),
+        _ => (total / parts, total % parts),
+    };
+    (0..parts).map(move |i| size + u32::from(i < remainder))
+}
+
+/// The offset and size of the part `index` when splitting `total` into `parts`, consistent with
+/// [`distribute`].
+///
+/// Returns `None` if `index` is not less than `parts`.
+pub fn checked_split(total: u32, parts: u32, index: u32) -> Option<(u32, u32)> {
+    if index >= parts {
+        return None;
+    }
+    let (size, remainder) = (total / parts, total % parts);
+    // Cannot overflow: the offset is at most `total`.
+    let offset = index * size + index.min(remainder);
+    Some((offset, size + u32::from(index < remainder)))
+}
+
+/// The number of columns and rows of a grid with `count` cells which is as square as possible:
+/// `ceil(sqrt(count))` columns and as many rows as needed, so only the last row may be
+/// incomplete. Returns `(0, 0)` if `count` is zero.
+///
+/// ```
+/// use river_layout_toolkit::math::grid_size;
+///
+/// assert_eq!(grid_size(5), (3, 2));
+/// for count in 1..=10 {
+///     let (columns, rows) = grid_size(count);
+///     assert!(columns >= rows);
+///     assert!(columns * rows >= count && columns * (rows - 1) < count);
+/// }
+/// ```
+pub fn grid_size(count: u32) -> (u32, u32) {
+    if count == 0 {
+        return (0, 0);
+    }
+    let columns = count.isqrt() + u32::from(count.isqrt().pow(2) != count);
+    (columns, count.div_ceil(columns))
+}
+
+/// Multiply `value` by `factor`, rounding down. The result saturates at `0` and `u32::MAX`, and
+/// is `0` if `factor` is NaN.
+pub fn scale(value: u32, factor: f64) -> u32 {
+    // Float to integer casts saturate.
+    (f64::from(value) * factor) as u32
+}
+
+/// Round fractional rectangles within a `width` by `height` area to pixels.
+///
+/// Every edge is rounded on its own and clamped to the area, so edges which coincide before
+/// rounding still coincide afterwards: rectangles which tile the area exactly are converted to
+/// rectangles which tile it exactly, with the outermost ones absorbing any rounding remainder.
+pub fn to_rectangle_grid(rects: &[FloatRect], width: u32, height: u32) -> Vec<Rectangle> {
+    let snap = |value: f64, total: u32| value.round().clamp(0.0, f64::from(total)) as u32;
+    rects
+        .iter()
+        .map(|rect| {
+            let left = snap(rect.x, width);
+            let top = snap(rect.y, height);
+            let right = snap(rect.x + rect.w, width).max(left);
+            let bottom = snap(rect.y + rect.h, height).max(top);
+            let (x, y) = (i32::try_from(left), i32::try_from(top));
+            Rectangle::new(
+                x.unwrap_or(i32::MAX),
+                y.unwrap_or(i32::MAX),
+                right - left,
+                bottom - top,
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distribute_remainder() {
+        assert_eq!(distribute(100, 3).collect::<Vec<_>>(), [34, 33, 33]);
+        assert_eq!(distribute(0, 5).collect::<Vec<_>>(), [0; 5]);
+        assert_eq!(distribute(2, 5).collect::<Vec<_>>(), [1, 1, 0, 0, 0]);
+        assert_eq!(distribute(100, 0).count(), 0);
+    }
+
+    #[test]
+    fn distribute_matches_checked_split() {
+        for total in [0, 1, 7, 100, 1080] {
+            for parts in 1..=12 {
+                let mut offset = 0;
+                for (index, size) in distribute(total, parts).enumerate() {
+                    assert_eq!(
+                        checked_split(total, parts, index as u32),
+                        Some((offset, size))
+                    );
+                    offset += size;
+                }
+                assert_eq!(offset, total);
+                assert_eq!(checked_split(total, parts, parts), None);
+            }
+        }
+    }
+
+    /// Split `rect` into `count` fractional rectangles which tile it, alternating the direction
+    /// and using uneven ratios so that the edges fall between pixels.
+    fn float_tiling(rect: FloatRect, count: u32, ratios: &[f64]) -> Vec<FloatRect> {
+        let mut rects = vec![rect];
+        for i in 1..count as usize {
+            let last = rects.pop().unwrap();
+            let ratio = ratios[i % ratios.len()];
+            let (first, second) = if i % 2 == 0 {
+                last.split_vertical(ratio)
+            } else {
+                last.split_horizontal(ratio)
+            };
+            rects.extend([first, second]);
+        }
+        rects
+    }
+
+    fn assert_tiles(rects: &[Rectangle], width: u32, height: u32) {
+        let area = Rectangle::new(0, 0, width, height);
+        for (i, a) in rects.iter().enumerate() {
+            if a.area() > 0 {
+                assert_eq!(a.intersection(&area), Some(*a), "{a:?} is out of bounds");
+            }
+            for b in &rects[i + 1..] {
+                assert_eq!(a.intersection(b), None, "{a:?} overlaps {b:?}");
+            }
+        }
+        assert_eq!(rects.iter().map(Rectangle::area).sum::<u64>(), area.area());
+    }
+
+    #[test]
+    fn to_rectangle_grid_tiles_area() {
+        let ratios = [1.0 / 3.0, 0.618, 0.5, 0.27, 0.9];
+        for (width, height) in [(1, 1), (7, 3), (100, 100), (1366, 768), (1920, 1080)] {
+            let area = FloatRect::new(0.0, 0.0, f64::from(width), f64::from(height));
+            for count in 1..=12 {
+                let rects = float_tiling(area, count, &ratios);
+                assert_tiles(&to_rectangle_grid(&rects, width, height), width, height);
+            }
+
+            // A grid of cells with fractional sizes.
+            let (columns, rows) = (7, 3);
+            let (w, h) = (area.w / f64::from(columns), area.h / f64::from(rows));
+            let cells: Vec<_> = (0..columns * rows)
+                .map(|i| {
+                    let (column, row) = (f64::from(i % columns), f64::from(i / columns));
+                    FloatRect::new(column * w, row * h, w, h)
+                })
+                .collect();
+            assert_tiles(&to_rectangle_grid(&cells, width, height), width, height);
+        }
+    }
+
+    #[test]
+    fn extreme_values() {
+        let max = u32::MAX;
+        assert_eq!(checked_split(max, max, max - 1), Some((max - 1, 1)));
+        assert_eq!(checked_split(max, 2, 1), Some((max / 2 + 1, max / 2)));
+        assert_eq!(checked_split(0, max, max - 1), Some((0, 0)));
+        assert_eq!(checked_split(max, max, max), None);
+        assert_eq!(distribute(max, max).take(2).collect::<Vec<_>>(), [1, 1]);
+        assert_eq!(
+            distribute(max, 2).collect::<Vec<_>>(),
+            [max / 2 + 1, max / 2]
+        );
+        assert_eq!(grid_size(max), (65536, 65536));
+        assert_eq!(scale(max, 1.0), max);
+        assert_eq!(scale(max, 2.0), max);
+        assert_eq!(scale(max, f64::INFINITY), max);
+        assert_eq!(scale(max, -1.0), 0);
+        assert_eq!(scale(max, f64::NAN), 0);
+    }
+}
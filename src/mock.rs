@@ -0,0 +1,1004 @@
+//! A minimal in-process compositor for integration tests, available with the `test-util`
+//! feature.
+//!
+//! [`MockServer`] speaks just enough of the Wayland protocol to advertise outputs, issue layout
+//! demands and record what the layout generator commits. It runs on its own thread, so the layout
+//! generator can be run with [`run_with_connection`](crate::run_with_connection) as usual.
+//!
+//! ```
+//! use river_layout_toolkit::layouts::Monocle;
+//! use river_layout_toolkit::mock::MockServer;
+//! use river_layout_toolkit::{run_with_connection, Rectangle};
+//! use std::convert::Infallible;
+//!
+//! let (server, conn) = MockServer::start(&["eDP-1"]).unwrap();
+//!
+//! let driver = std::thread::spawn(move || {
+//!     let serial = server.layout_demand("eDP-1", 2, 1920, 1080, 1).unwrap();
+//!     let commit = server.wait_commit(serial).unwrap();
+//!     server.disconnect();
+//!     commit
+//! });
+//!
+//! let layout = river_layout_toolkit::FnLayout::new("monocle", |ctx| {
+//!     Ok::<_, Infallible>(Monocle.layout(ctx))
+//! });
+//! // The layout generator stops once the server disconnects.
+//! assert!(run_with_connection(conn, layout).is_err());
+//!
+//! let commit = driver.join().unwrap();
+//! assert_eq!(commit.output, "eDP-1");
+//! assert_eq!(commit.views, vec![Rectangle::new(0, 0, 1920, 1080); 2]);
+//! ```
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use wayrs_client::{ConnectError, Connection};
+
+use crate::Rectangle;
+
+/// How long [`MockServer`] waits for the layout generator before giving up.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+const DISPLAY_ID: u32 = 1;
+const LAYOUT_MANAGER_GLOBAL: u32 = 1;
+const LAYOUT_MANAGER_VERSION: u32 = 2;
+const OUTPUT_VERSION: u32 = 4;
+
+/// A layout committed by the layout generator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    pub output: String,
+    pub serial: u32,
+    pub layout_name: String,
+    pub views: Vec<Rectangle>,
+}
+
+/// A handle to a running mock compositor. Cloning it gives another handle to the same server.
+#[derive(Clone)]
+pub struct MockServer {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    inner: Mutex<Inner>,
+    changed: Condvar,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Interface {
+    Registry,
+    Output { global: u32 },
+    LayoutManager,
+    Layout,
+}
+
+struct MockOutput {
+    name: String,
+    global: u32,
+}
+
+struct MockLayout {
+    output: String,
+    version: u32,
+}
+
+struct Inner {
+    writer: UnixStream,
+    closed: bool,
+    objects: HashMap<u32, (Interface, u32)>,
+    registry: Option<u32>,
+    outputs: Vec<MockOutput>,
+    next_global: u32,
+    layouts: HashMap<u32, MockLayout>,
+    pending_views: HashMap<u32, Vec<Rectangle>>,
+    commits: Vec<Commit>,
+    next_serial: u32,
+    layout_manager_version: u32,
+}
+
+impl MockServer {
+    /// Start a server with the given outputs and connect to it.
+    ///
+    /// Like [`LayoutRunnerBuilder::socket`](crate::LayoutRunnerBuilder::socket), this temporarily
+    /// sets `WAYLAND_SOCKET`, so other threads must not read the environment at the same time.
+    pub fn start(outputs: &[&str]) -> Result<(MockServer, Connection<()>), ConnectError> {
+        Self::start_with_version(outputs, LAYOUT_MANAGER_VERSION)
+    }
+
+    /// Same as [`start`](Self::start), but advertise the given version of
+    /// `river_layout_manager_v3`, e.g. `1` to test the behavior with older river versions.
+    pub fn start_with_version(
+        outputs: &[&str],
+        layout_manager_version: u32,
+    ) -> Result<(MockServer, Connection<()>), ConnectError> {
+        let (server_end, client_end) = UnixStream::pair()?;
+        let reader = server_end.try_clone()?;
+        let mut inner = Inner {
+            writer: server_end,
+            closed: false,
+            objects: HashMap::new(),
+            registry: None,
+            outputs: Vec::new(),
+            next_global: LAYOUT_MANAGER_GLOBAL + 1,
+            layouts: HashMap::new(),
+            pending_views: HashMap::new(),
+            commits: Vec::new(),
+            next_serial: 1,
+            layout_manager_version,
+        };
+        for &name in outputs {
+            inner.add_output(name);
+        }
+        let server = MockServer {
+            shared: Arc::new(Shared {
+                inner: Mutex::new(inner),
+                changed: Condvar::new(),
+            }),
+        };
+
+        let shared = server.shared.clone();
+        thread::spawn(move || serve(reader, &shared));
+
+        let conn = crate::connect_with_stream(client_end)?;
+        Ok((server, conn))
+    }
+
+    /// Advertise a new output.
+    pub fn add_output(&self, name: &str) {
+        self.lock().add_output(name);
+    }
+
+    /// Remove an output, as if it was unplugged.
+    pub fn remove_output(&self, name: &str) {
+        let mut inner = self.lock();
+        let Some(index) = inner.outputs.iter().position(|o| o.name == name) else {
+            return;
+        };
+        let output = inner.outputs.remove(index);
+        if let Some(registry) = inner.registry {
+            inner.send(Message::new(registry, 1).uint(output.global));
+        }
+        inner.layouts.retain(|_, layout| layout.output != name);
+    }
+
+    /// Send a layout demand to the layout generator of an output and return its serial.
+    ///
+    /// Waits until the layout generator has created its layout object for the output.
+    pub fn layout_demand(
+        &self,
+        output: &str,
+        view_count: u32,
+        usable_width: u32,
+        usable_height: u32,
+        tags: u32,
+    ) -> io::Result<u32> {
+        let mut inner = self.wait_for(|inner| inner.layout_of(output).is_some())?;
+        let (id, _) = inner.layout_of(output).unwrap();
+        let serial = inner.next_serial;
+        inner.next_serial += 1;
+        inner.send(
+            Message::new(id, 1)
+                .uint(view_count)
+                .uint(usable_width)
+                .uint(usable_height)
+                .uint(tags)
+                .uint(serial),
+        );
+        Ok(serial)
+    }
+
+    /// Send a command to the layout generator of an output, as if `riverctl send-layout-cmd` was
+    /// run with the given focused tags.
+    pub fn user_command(&self, output: &str, command: &str, tags: u32) -> io::Result<()> {
+        let mut inner = self.wait_for(|inner| inner.layout_of(output).is_some())?;
+        let (id, version) = inner.layout_of(output).unwrap();
+        if version >= 2 {
+            inner.send(Message::new(id, 3).uint(tags));
+        }
+        inner.send(Message::new(id, 2).string(command));
+        Ok(())
+    }
+
+    /// Wait for the layout generator to answer the layout demand with the given serial.
+    pub fn wait_commit(&self, serial: u32) -> io::Result<Commit> {
+        let inner = self.wait_for(|inner| inner.commits.iter().any(|c| c.serial == serial))?;
+        Ok(inner
+            .commits
+            .iter()
+            .find(|c| c.serial == serial)
+            .unwrap()
+            .clone())
+    }
+
+    /// All layouts committed so far, in order.
+    pub fn commits(&self) -> Vec<Commit> {
+        self.lock().commits.clone()
+    }
+
+    /// Like [`commits`](Self::commits), but forget the returned layouts, e.g. to keep memory use
+    /// constant while sending many demands.
+    pub fn take_commits(&self) -> Vec<Commit> {
+        mem::take(&mut self.lock().commits)
+    }
+
+    /// Close the connection. The layout generator will see an IO error.
+    pub fn disconnect(&self) {
+        let mut inner = self.lock();
+        inner.closed = true;
+        let _ = inner.writer.shutdown(Shutdown::Both);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.shared.inner.lock().unwrap()
+    }
+
+    fn wait_for(&self, mut cond: impl FnMut(&Inner) -> bool) -> io::Result<MutexGuard<'_, Inner>> {
+        let deadline = Instant::now() + TIMEOUT;
+        let mut inner = self.lock();
+        while !cond(&inner) {
+            if inner.closed {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout.is_zero() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            inner = self.shared.changed.wait_timeout(inner, timeout).unwrap().0;
+        }
+        Ok(inner)
+    }
+}
+
+fn serve(mut reader: UnixStream, shared: &Shared) {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 4096];
+    loop {
+        let n = match reader.read(&mut chunk) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+        buf.extend_from_slice(&chunk[..n]);
+
+        let mut inner = shared.inner.lock().unwrap();
+        let mut offset = 0;
+        while buf.len() - offset >= 8 {
+            let object = read_u32(&buf[offset..]);
+            let size_opcode = read_u32(&buf[offset + 4..]);
+            let size = (size_opcode >> 16) as usize;
+            if size < 8 || buf.len() - offset < size {
+                break;
+            }
+            let opcode = (size_opcode & 0xffff) as u16;
+            let args = Args(&buf[offset + 8..offset + size]);
+            inner.handle_request(object, opcode, args);
+            offset += size;
+        }
+        buf.drain(..offset);
+        drop(inner);
+        shared.changed.notify_all();
+    }
+    shared.inner.lock().unwrap().closed = true;
+    shared.changed.notify_all();
+}
+
+impl Inner {
+    fn add_output(&mut self, name: &str) {
+        let global = self.next_global;
+        self.next_global += 1;
+        self.outputs.push(MockOutput {
+            name: name.to_owned(),
+            global,
+        });
+        if let Some(registry) = self.registry {
+            self.send_output_global(registry, global);
+        }
+    }
+
+    fn send_output_global(&mut self, registry: u32, global: u32) {
+        self.send(
+            Message::new(registry, 0)
+                .uint(global)
+                .string("wl_output")
+                .uint(OUTPUT_VERSION),
+        );
+    }
+
+    fn layout_of(&self, output: &str) -> Option<(u32, u32)> {
+        self.layouts
+            .iter()
+            .find(|(_, layout)| layout.output == output)
+            .map(|(&id, layout)| (id, layout.version))
+    }
+
+    fn handle_request(&mut self, object: u32, opcode: u16, mut args: Args<'_>) {
+        if object == DISPLAY_ID {
+            match opcode {
+                // sync
+                0 => {
+                    let callback = args.uint();
+                    self.send(Message::new(callback, 0).uint(0));
+                    self.send(Message::new(DISPLAY_ID, 1).uint(callback));
+                }
+                // get_registry
+                1 => {
+                    let registry = args.uint();
+                    self.objects.insert(registry, (Interface::Registry, 1));
+                    self.registry = Some(registry);
+                    self.send(
+                        Message::new(registry, 0)
+                            .uint(LAYOUT_MANAGER_GLOBAL)
+                            .string("river_layout_manager_v3")
+                            .uint(self.layout_manager_version),
+                    );
+                    let globals: Vec<u32> = self.outputs.iter().map(|o| o.global).collect();
+                    for global in globals {
+                        self.send_output_global(registry, global);
+                    }
+                }
+                _ => (),
+            }
+            return;
+        }
+
+        let Some(&(interface, version)) = self.objects.get(&object) else {
+            return;
+        };
+        match (interface, opcode) {
+            // wl_registry.bind
+            (Interface::Registry, 0) => {
+                let global = args.uint();
+                let _interface = args.string();
+                let version = args.uint();
+                let id = args.uint();
+                if global == LAYOUT_MANAGER_GLOBAL {
+                    self.objects.insert(id, (Interface::LayoutManager, version));
+                } else if let Some(output) = self.outputs.iter().find(|o| o.global == global) {
+                    let name = output.name.clone();
+                    self.objects
+                        .insert(id, (Interface::Output { global }, version));
+                    self.send(Message::new(id, 1).uint(1).int(1920).int(1080).int(60000));
+                    if version >= 2 {
+                        self.send(Message::new(id, 3).int(1));
+                    }
+                    if version >= 4 {
+                        self.send(Message::new(id, 4).string(&name));
+                    }
+                    if version >= 2 {
+                        self.send(Message::new(id, 2));
+                    }
+                }
+            }
+            // wl_output.release, river_layout_manager_v3.destroy
+            (Interface::Output { .. }, 0) | (Interface::LayoutManager, 0) => {
+                self.destroy(object);
+            }
+            // river_layout_manager_v3.get_layout
+            (Interface::LayoutManager, 1) => {
+                let id = args.uint();
+                let output = args.uint();
+                let _namespace = args.string();
+                let Some(&(Interface::Output { global }, _)) = self.objects.get(&output) else {
+                    return;
+                };
+                let Some(output) = self.outputs.iter().find(|o| o.global == global) else {
+                    return;
+                };
+                let output = output.name.clone();
+                self.objects.insert(id, (Interface::Layout, version));
+                self.layouts.insert(id, MockLayout { output, version });
+            }
+            // river_layout_v3.destroy
+            (Interface::Layout, 0) => {
+                self.layouts.remove(&object);
+                self.destroy(object);
+            }
+            // river_layout_v3.push_view_dimensions
+            (Interface::Layout, 1) => {
+                let x = args.int();
+                let y = args.int();
+                let width = args.uint();
+                let height = args.uint();
+                self.pending_views
+                    .entry(object)
+                    .or_default()
+                    .push(Rectangle::new(x, y, width, height));
+            }
+            // river_layout_v3.commit
+            (Interface::Layout, 2) => {
+                let layout_name = args.string();
+                let serial = args.uint();
+                let views = self.pending_views.remove(&object).unwrap_or_default();
+                if let Some(layout) = self.layouts.get(&object) {
+                    self.commits.push(Commit {
+                        output: layout.output.clone(),
+                        serial,
+                        layout_name,
+                        views,
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn destroy(&mut self, object: u32) {
+        self.objects.remove(&object);
+        self.send(Message::new(DISPLAY_ID, 1).uint(object));
+    }
+
+    fn send(&mut self, message: Message) {
+        if !self.closed && self.writer.write_all(&message.finish()).is_err() {
+            self.closed = true;
+        }
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_ne_bytes(bytes[..4].try_into().unwrap())
+}
+
+/// The arguments of a request.
+struct Args<'a>(&'a [u8]);
+
+impl Args<'_> {
+    fn uint(&mut self) -> u32 {
+        let value = read_u32(self.0);
+        self.0 = &self.0[4..];
+        value
+    }
+
+    fn int(&mut self) -> i32 {
+        self.uint() as i32
+    }
+
+    fn string(&mut self) -> String {
+        let len = self.uint() as usize;
+        let string = String::from_utf8_lossy(&self.0[..len.saturating_sub(1)]).into_owned();
+        self.0 = &self.0[len.next_multiple_of(4)..];
+        string
+    }
+}
+
+/// An event being serialized.
+struct Message(Vec<u8>);
+
+impl Message {
+    fn new(object: u32, opcode: u16) -> Self {
+        let mut buf = Vec::with_capacity(32);
+        buf.extend_from_slice(&object.to_ne_bytes());
+        buf.extend_from_slice(&u32::from(opcode).to_ne_bytes());
+        Self(buf)
+    }
+
+    fn uint(mut self, value: u32) -> Self {
+        self.0.extend_from_slice(&value.to_ne_bytes());
+        self
+    }
+
+    fn int(self, value: i32) -> Self {
+        self.uint(value as u32)
+    }
+
+    fn string(mut self, value: &str) -> Self {
+        let len = value.len() + 1;
+        self = self.uint(len as u32);
+        self.0.extend_from_slice(value.as_bytes());
+        self.0
+            .resize(self.0.len() + len.next_multiple_of(4) - value.len(), 0);
+        self
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let size = self.0.len() as u32;
+        let opcode = read_u32(&self.0[4..]);
+        self.0[4..8].copy_from_slice(&(size << 16 | opcode).to_ne_bytes());
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::JoinHandle;
+
+    use super::*;
+    use crate::{
+        Error, ErrorPolicy, GeneratedLayout, Layout, LayoutContext, LayoutRunner, OutputGeometry,
+        OutputInfo, OutputMode, OutputTransform, RunOptions, StopHandle, Tags, UserCmdOutcome,
+    };
+
+    /// Demands with these tags make [`Recorder`] fail.
+    const FAIL: u32 = 1 << 31;
+    /// Demands with these tags make [`Recorder`] generate views without a width.
+    const ZERO_WIDTH: u32 = 1 << 30;
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("failed on purpose")]
+    struct Failed;
+
+    #[derive(Debug, Clone)]
+    enum Call {
+        Start,
+        Shutdown,
+        Cmd {
+            output: String,
+            cmd: String,
+            tags: Option<Tags>,
+        },
+        Generate {
+            output: OutputInfo,
+            view_count: u32,
+        },
+    }
+
+    /// A monocle layout which records every call.
+    struct Recorder {
+        namespace: &'static str,
+        calls: Arc<Mutex<Vec<Call>>>,
+    }
+
+    impl Recorder {
+        fn new() -> (Self, Arc<Mutex<Vec<Call>>>) {
+            let calls = Arc::default();
+            let layout = Self {
+                namespace: "test",
+                calls: Arc::clone(&calls),
+            };
+            (layout, calls)
+        }
+
+        fn record(&self, call: Call) {
+            self.calls.lock().unwrap().push(call);
+        }
+    }
+
+    impl Layout for Recorder {
+        type Error = Failed;
+
+        const NAMESPACE: &'static str = "test";
+
+        fn namespace(&self) -> &str {
+            self.namespace
+        }
+
+        fn on_start(&mut self) -> Result<(), Failed> {
+            self.record(Call::Start);
+            Ok(())
+        }
+
+        fn on_shutdown(&mut self) {
+            self.record(Call::Shutdown);
+        }
+
+        fn user_cmd(
+            &mut self,
+            cmd: String,
+            tags: Option<Tags>,
+            output: &str,
+        ) -> Result<UserCmdOutcome, Failed> {
+            let output = output.to_owned();
+            self.record(Call::Cmd { output, cmd, tags });
+            Ok(UserCmdOutcome::Handled)
+        }
+
+        fn generate_layout(&mut self, ctx: &LayoutContext<'_>) -> Result<GeneratedLayout, Failed> {
+            self.record(Call::Generate {
+                output: ctx.output.clone(),
+                view_count: ctx.view_count,
+            });
+            if ctx.tags == Tags(FAIL) {
+                return Err(Failed);
+            }
+            let mut view = ctx.usable_area();
+            if ctx.tags == Tags(ZERO_WIDTH) {
+                view.width = 0;
+            }
+            let views = vec![view; ctx.view_count as usize];
+            Ok(GeneratedLayout::from_views("test", views))
+        }
+    }
+
+    /// A layout generator running on its own thread.
+    struct Running {
+        stop: StopHandle,
+        thread: JoinHandle<Result<(), Error<Failed>>>,
+    }
+
+    impl Running {
+        fn start(conn: Connection<()>, layout: Recorder, options: RunOptions) -> Self {
+            let mut runner = LayoutRunner::builder()
+                .connection(conn)
+                .options(options)
+                .build(layout)
+                .unwrap();
+            let stop = runner.stop_handle().unwrap();
+            let thread = thread::spawn(move || runner.run());
+            Self { stop, thread }
+        }
+
+        fn stop(self) -> Result<(), Error<Failed>> {
+            self.stop.stop();
+            self.join()
+        }
+
+        fn join(self) -> Result<(), Error<Failed>> {
+            self.thread.join().unwrap()
+        }
+    }
+
+    impl MockServer {
+        /// Send an event to the layout object of `output`, bypassing the checks of the public
+        /// methods.
+        fn send_to_layout(&self, output: &str, message: impl FnOnce(u32) -> Message) {
+            let mut inner = self
+                .wait_for(|inner| inner.layout_of(output).is_some())
+                .unwrap();
+            let (id, _) = inner.layout_of(output).unwrap();
+            inner.send(message(id));
+        }
+
+        /// Send an event to the `wl_output` bound by the layout generator.
+        fn send_to_output(&self, output: &str, message: impl FnOnce(u32) -> Message) {
+            let mut inner = self.lock();
+            let global = inner
+                .outputs
+                .iter()
+                .find(|o| o.name == output)
+                .unwrap()
+                .global;
+            let bound = Interface::Output { global };
+            let (&id, _) = inner
+                .objects
+                .iter()
+                .find(|(_, (interface, _))| *interface == bound)
+                .unwrap();
+            inner.send(message(id));
+        }
+    }
+
+    fn commands(calls: &Mutex<Vec<Call>>) -> Vec<(String, String, Option<Tags>)> {
+        let calls = calls.lock().unwrap();
+        calls
+            .iter()
+            .filter_map(|call| match call {
+                Call::Cmd { output, cmd, tags } => Some((output.clone(), cmd.clone(), *tags)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn command_tags_per_output() {
+        let (server, conn) = MockServer::start(&["A", "B"]).unwrap();
+        let (layout, calls) = Recorder::new();
+        let running = Running::start(conn, layout, RunOptions::default());
+
+        let tags =
+            |output, tags| server.send_to_layout(output, |id| Message::new(id, 3).uint(tags));
+        let cmd = |output, cmd| server.send_to_layout(output, |id| Message::new(id, 2).string(cmd));
+        tags("A", 0b01);
+        tags("B", 0b10);
+        cmd("B", "b1");
+        cmd("A", "a1");
+        tags("A", 0b100);
+        cmd("B", "b2");
+        cmd("A", "a2");
+        // Events are handled in order, so the commands are done once the layout is committed.
+        let serial = server.layout_demand("A", 1, 100, 100, 1).unwrap();
+        server.wait_commit(serial).unwrap();
+
+        let cmd =
+            |output: &str, cmd: &str, tags| (output.to_owned(), cmd.to_owned(), Some(Tags(tags)));
+        assert_eq!(
+            commands(&calls),
+            [
+                cmd("B", "b1", 0b10),
+                cmd("A", "a1", 0b01),
+                cmd("B", "b2", 0b10),
+                cmd("A", "a2", 0b100),
+            ]
+        );
+        let last = calls.lock().unwrap().last().cloned();
+        assert!(
+            matches!(&last, Some(Call::Generate { output, view_count: 1 }) if output.name == "A"),
+            "{last:?}"
+        );
+        running.stop().unwrap();
+    }
+
+    #[test]
+    fn generate_error_is_committed() {
+        let (server, conn) = MockServer::start(&["A"]).unwrap();
+        let (layout, _) = Recorder::new();
+        let options = RunOptions {
+            on_generate_error: ErrorPolicy::Warn,
+            ..RunOptions::default()
+        };
+        let running = Running::start(conn, layout, options);
+
+        let serial = server.layout_demand("A", 2, 100, 100, 1).unwrap();
+        let previous = server.wait_commit(serial).unwrap();
+        assert_eq!(previous.views.len(), 2);
+
+        // The previous layout still has the right number of views.
+        let serial = server.layout_demand("A", 2, 100, 100, FAIL).unwrap();
+        let commit = server.wait_commit(serial).unwrap();
+        assert_eq!(commit.views, previous.views);
+
+        // It does not anymore, so nothing is sent.
+        let serial = server.layout_demand("A", 3, 100, 100, FAIL).unwrap();
+        assert!(server.wait_commit(serial).unwrap().views.is_empty());
+
+        running.stop().unwrap();
+    }
+
+    #[test]
+    fn version_1() {
+        let (server, conn) = MockServer::start_with_version(&["A"], 1).unwrap();
+        let (layout, calls) = Recorder::new();
+        let running = Running::start(conn, layout, RunOptions::default());
+
+        server.user_command("A", "cmd", 0b10).unwrap();
+        let serial = server.layout_demand("A", 2, 100, 100, 1).unwrap();
+        let commit = server.wait_commit(serial).unwrap();
+        assert_eq!(commit.views, [Rectangle::new(0, 0, 100, 100); 2]);
+        assert_eq!(commands(&calls), [("A".to_owned(), "cmd".to_owned(), None)]);
+
+        running.stop().unwrap();
+    }
+
+    #[test]
+    fn events_after_output_removal() {
+        let (server, conn) = MockServer::start(&["A", "B"]).unwrap();
+        let (layout, calls) = Recorder::new();
+        let running = Running::start(conn, layout, RunOptions::default());
+
+        let (id, _) = server
+            .wait_for(|inner| inner.layout_of("A").is_some())
+            .unwrap()
+            .layout_of("A")
+            .unwrap();
+        server.remove_output("A");
+        // The compositor may still send events for the layout object of the removed output.
+        let mut inner = server.lock();
+        inner.send(Message::new(id, 3).uint(1));
+        inner.send(Message::new(id, 2).string("cmd"));
+        inner.send(
+            Message::new(id, 1)
+                .uint(1)
+                .uint(100)
+                .uint(100)
+                .uint(1)
+                .uint(1000),
+        );
+        drop(inner);
+
+        let serial = server.layout_demand("B", 1, 100, 100, 1).unwrap();
+        server.wait_commit(serial).unwrap();
+        assert!(server.commits().iter().all(|commit| commit.output == "B"));
+        let calls = calls.lock().unwrap();
+        assert!(
+            calls.iter().all(|call| match call {
+                Call::Cmd { output, .. } => output == "B",
+                Call::Generate { output, .. } => output.name == "B",
+                Call::Start | Call::Shutdown => true,
+            }),
+            "{calls:?}"
+        );
+        drop(calls);
+
+        running.stop().unwrap();
+    }
+
+    #[test]
+    fn invalid_namespace() {
+        let (server, conn) = MockServer::start(&["A"]).unwrap();
+        let (mut layout, calls) = Recorder::new();
+        layout.namespace = "in\0valid";
+        let Err(err) = LayoutRunner::builder().connection(conn).build(layout) else {
+            panic!("the namespace was accepted");
+        };
+        assert!(
+            matches!(&err, Error::InvalidNamespace(ns) if ns == "in\0valid"),
+            "{err}"
+        );
+        assert!(calls.lock().unwrap().is_empty());
+        assert!(server.lock().layouts.is_empty());
+    }
+
+    #[test]
+    fn output_properties() {
+        let (server, conn) = MockServer::start(&["A"]).unwrap();
+        let (layout, calls) = Recorder::new();
+        let running = Running::start(conn, layout, RunOptions::default());
+        let demand_info = || {
+            let serial = server.layout_demand("A", 1, 100, 100, 1).unwrap();
+            server.wait_commit(serial).unwrap();
+            let calls = calls.lock().unwrap();
+            match calls.last() {
+                Some(Call::Generate { output, .. }) => output.clone(),
+                call => panic!("{call:?}"),
+            }
+        };
+
+        let initial = demand_info();
+        assert_eq!(initial.name, "A");
+        assert_eq!((initial.make.as_str(), initial.model.as_str()), ("A", "A"));
+        assert_eq!(initial.scale, 1);
+        let mode = OutputMode {
+            width: 1920,
+            height: 1080,
+            refresh: 60000,
+        };
+        assert_eq!(initial.mode, Some(mode));
+        assert_eq!(initial.geometry, None);
+        assert_eq!(initial.transform, OutputTransform::Normal);
+
+        // Only the scale changes, and the second one is not applied without `done`.
+        server.send_to_output("A", |id| Message::new(id, 3).int(2));
+        server.send_to_output("A", |id| Message::new(id, 2));
+        server.send_to_output("A", |id| Message::new(id, 3).int(3));
+        let info = demand_info();
+        assert_eq!(info.scale, 2);
+        assert_eq!(info.mode, Some(mode));
+
+        server.send_to_output("A", |id| {
+            Message::new(id, 0)
+                .int(10)
+                .int(20)
+                .int(300)
+                .int(200)
+                .int(0)
+                .string("ACME")
+                .string("X1")
+                .int(1)
+        });
+        server.send_to_output("A", |id| Message::new(id, 2));
+        let info = demand_info();
+        assert_eq!(info.name, "A");
+        assert_eq!((info.make.as_str(), info.model.as_str()), ("ACME", "X1"));
+        assert_eq!(info.scale, 3);
+        assert_eq!(info.mode, Some(mode));
+        let geometry = OutputGeometry {
+            x: 10,
+            y: 20,
+            physical_width: 300,
+            physical_height: 200,
+        };
+        assert_eq!(info.geometry, Some(geometry));
+        assert_eq!(info.transform, OutputTransform::Rotate90);
+
+        running.stop().unwrap();
+    }
+
+    #[test]
+    fn shutdown_on_every_exit() {
+        let shutdowns = |calls: &Mutex<Vec<Call>>| {
+            let calls = calls.lock().unwrap();
+            calls
+                .iter()
+                .filter(|call| matches!(call, Call::Shutdown))
+                .count()
+        };
+
+        let (_server, conn) = MockServer::start(&["A"]).unwrap();
+        let (layout, calls) = Recorder::new();
+        Running::start(conn, layout, RunOptions::default())
+            .stop()
+            .unwrap();
+        assert_eq!(shutdowns(&calls), 1);
+
+        let (server, conn) = MockServer::start(&["A"]).unwrap();
+        let (layout, calls) = Recorder::new();
+        let running = Running::start(conn, layout, RunOptions::default());
+        server.disconnect();
+        let err = running.join().unwrap_err();
+        assert!(matches!(err, Error::Connection { .. }), "{err}");
+        assert_eq!(shutdowns(&calls), 1);
+
+        let (_server, conn) = MockServer::start(&["A"]).unwrap();
+        let (layout, calls) = Recorder::new();
+        drop(LayoutRunner::builder().connection(conn).build(layout));
+        assert_eq!(shutdowns(&calls), 1);
+    }
+
+    #[cfg(feature = "control-socket")]
+    #[test]
+    fn control_socket() {
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::Shutdown;
+        use std::os::unix::net::UnixStream;
+
+        let connect = |path: &std::path::Path| {
+            let client = UnixStream::connect(path).unwrap();
+            client.set_read_timeout(Some(TIMEOUT)).unwrap();
+            let replies = BufReader::new(client.try_clone().unwrap());
+            (client, replies)
+        };
+        let read_reply = |replies: &mut BufReader<UnixStream>| {
+            let mut reply = String::new();
+            replies.read_line(&mut reply).unwrap();
+            reply
+        };
+
+        let path = std::env::temp_dir().join(format!("rlt-control-{}.sock", std::process::id()));
+        let (server, conn) = MockServer::start(&["A"]).unwrap();
+        let (layout, calls) = Recorder::new();
+        let options = RunOptions {
+            control_socket: Some(path.clone()),
+            ..RunOptions::default()
+        };
+        let running = Running::start(conn, layout, options);
+        drop(
+            server
+                .wait_for(|inner| inner.layout_of("A").is_some())
+                .unwrap(),
+        );
+
+        // Commands are answered while the client is still connected.
+        let (mut first, mut first_replies) = connect(&path);
+        first.write_all(b"main-count +1\n\nmain-").unwrap();
+        assert_eq!(read_reply(&mut first_replies), "ok\n");
+
+        // An incomplete command does not hold up other clients.
+        let (mut second, mut second_replies) = connect(&path);
+        second.write_all(b"main-count -1\n").unwrap();
+        assert_eq!(read_reply(&mut second_replies), "ok\n");
+
+        // The last command does not have to end with a newline.
+        first.write_all(b"ratio +0.1").unwrap();
+        first.shutdown(Shutdown::Write).unwrap();
+        assert_eq!(read_reply(&mut first_replies), "ok\n");
+        assert_eq!(read_reply(&mut first_replies), "");
+
+        let (mut long, mut long_replies) = connect(&path);
+        long.write_all(&[b'a'; 5000]).unwrap();
+        let mut reply = String::new();
+        long_replies.read_to_string(&mut reply).unwrap();
+        assert!(reply.starts_with("error: "), "{reply}");
+
+        let cmd = |cmd: &str| ("A".to_owned(), cmd.to_owned(), None);
+        assert_eq!(
+            commands(&calls),
+            [
+                cmd("main-count +1"),
+                cmd("main-count -1"),
+                cmd("main-ratio +0.1"),
+            ]
+        );
+        running.stop().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn zero_size_before_clamping() {
+        let options = |policy| RunOptions {
+            clamp_to_usable: true,
+            on_zero_size_view: Some(policy),
+            ..RunOptions::default()
+        };
+
+        // Clamping would widen the views to 1 pixel, hiding the bug.
+        let (server, conn) = MockServer::start(&["A"]).unwrap();
+        let (layout, _) = Recorder::new();
+        let running = Running::start(conn, layout, options(ErrorPolicy::Warn));
+        let serial = server.layout_demand("A", 2, 100, 100, ZERO_WIDTH).unwrap();
+        let commit = server.wait_commit(serial).unwrap();
+        assert_eq!(commit.views, [Rectangle::new(0, 0, 0, 100); 2]);
+        running.stop().unwrap();
+
+        let (server, conn) = MockServer::start(&["A"]).unwrap();
+        let (layout, _) = Recorder::new();
+        let running = Running::start(conn, layout, options(ErrorPolicy::Error));
+        server.layout_demand("A", 2, 100, 100, ZERO_WIDTH).unwrap();
+        let err = running.join().unwrap_err();
+        assert!(matches!(err, Error::ZeroSizeView { index: 0, .. }), "{err}");
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::{Demand, GeneratedLayout, Layout, LayoutContext, Tags, UserCmdOutcome, ViewStream};
+
+/// Runs a separate instance of a layout on each output.
+///
+/// An instance is created with [`Default`] when its output is added, and dropped when the output
+/// goes away. This way the layout itself only has to manage the state of a single output.
+///
+/// Every instance is passed its own state file, `<namespace>-<output>.json`, with
+/// [`Layout::set_state_path`], and is started with [`Layout::on_start`] right after being
+/// created. Since that happens outside of [`Layout::on_start`] of the `PerOutput`, errors of
+/// instances created later only produce a warning.
+///
+/// The namespace is always [`Layout::NAMESPACE`], because it is registered before any instance
+/// exists.
+#[derive(Debug, Default)]
+pub struct PerOutput<L> {
+    layouts: HashMap<String, L>,
+    state_path: Option<PathBuf>,
+    started: bool,
+}
+
+impl<L: Layout + Default> PerOutput<L> {
+    pub fn new() -> Self {
+        Self {
+            layouts: HashMap::new(),
+            state_path: None,
+            started: false,
+        }
+    }
+
+    /// The instance of the given output, if it exists.
+    pub fn get(&self, output: &str) -> Option<&L> {
+        self.layouts.get(output)
+    }
+
+    /// The instance of the given output, created if it does not exist yet.
+    pub fn get_mut(&mut self, output: &str) -> &mut L {
+        if !self.layouts.contains_key(output) {
+            let mut layout = L::default();
+            if let Some(path) = &self.state_path {
+                layout.set_state_path(&instance_state_path(path, output));
+            }
+            if self.started {
+                if let Err(e) = layout.on_start() {
+                    log::warn!("could not start the layout of output '{output}': {e}");
+                }
+            }
+            self.layouts.insert(output.to_owned(), layout);
+        }
+        self.layouts.get_mut(output).unwrap()
+    }
+
+    /// The instance of the given output, created if it does not exist yet, together with the
+    /// instances of all other outputs. This allows mutating the state of one output based on the
+    /// state of the others.
+    pub fn split_mut(&mut self, output: &str) -> (&mut L, Vec<(&str, &L)>) {
+        self.get_mut(output);
+        let mut current = None;
+        let mut others = Vec::with_capacity(self.layouts.len() - 1);
+        for (name, layout) in &mut self.layouts {
+            if name == output {
+                current = Some(layout);
+            } else {
+                others.push((name.as_str(), &*layout));
+            }
+        }
+        (current.unwrap(), others)
+    }
+
+    /// Iterate over the output names and their instances.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &L)> {
+        self.layouts
+            .iter()
+            .map(|(output, layout)| (output.as_str(), layout))
+    }
+}
+
+impl<L: Layout + Default> Layout for PerOutput<L> {
+    type Error = L::Error;
+
+    const NAMESPACE: &'static str = L::NAMESPACE;
+
+    fn user_cmd(
+        &mut self,
+        cmd: String,
+        tags: Option<Tags>,
+        output: &str,
+    ) -> Result<UserCmdOutcome, Self::Error> {
+        self.get_mut(output).user_cmd(cmd, tags, output)
+    }
+
+    fn generate_layout(&mut self, ctx: &LayoutContext<'_>) -> Result<GeneratedLayout, Self::Error> {
+        self.get_mut(&ctx.output.name).generate_layout(ctx)
+    }
+
+    fn generate_layout_into(&mut self, demand: &mut Demand<'_>) -> Result<(), Self::Error> {
+        let output = &demand.ctx().output.name;
+        self.get_mut(output).generate_layout_into(demand)
+    }
+
+    fn stream_layout(&mut self, stream: &mut ViewStream<'_>) -> Option<Result<(), Self::Error>> {
+        let output = &stream.ctx().output.name;
+        self.get_mut(output).stream_layout(stream)
+    }
+
+    fn on_start(&mut self) -> Result<(), Self::Error> {
+        self.started = true;
+        for layout in self.layouts.values_mut() {
+            layout.on_start()?;
+        }
+        Ok(())
+    }
+
+    fn set_state_path(&mut self, path: &Path) {
+        for (output, layout) in &mut self.layouts {
+            layout.set_state_path(&instance_state_path(path, output));
+        }
+        self.state_path = Some(path.to_owned());
+    }
+
+    fn on_shutdown(&mut self) {
+        for layout in self.layouts.values_mut() {
+            layout.on_shutdown();
+        }
+    }
+
+    fn output_added(&mut self, output: &str) {
+        self.get_mut(output).output_added(output);
+    }
+
+    fn usable_area_changed(&mut self, output: &str, old: Option<(u32, u32)>, new: (u32, u32)) {
+        self.get_mut(output).usable_area_changed(output, old, new);
+    }
+
+    fn output_removed(&mut self, output: &str) {
+        if let Some(mut layout) = self.layouts.remove(output) {
+            layout.output_removed(output);
+        }
+    }
+}
+
+/// `<namespace>-<output>.json` next to `<namespace>.json`. Slashes in the output name are replaced,
+/// so that the file stays in the same directory.
+fn instance_state_path(path: &Path, output: &str) -> PathBuf {
+    let namespace = path.file_stem().unwrap_or_default().to_string_lossy();
+    let output = output.replace('/', "_");
+    path.with_file_name(format!("{namespace}-{output}.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Test {
+        state_path: Option<PathBuf>,
+        started: u32,
+        added: Vec<String>,
+    }
+
+    impl Layout for Test {
+        type Error = Infallible;
+
+        const NAMESPACE: &'static str = "test";
+
+        fn on_start(&mut self) -> Result<(), Self::Error> {
+            self.started += 1;
+            Ok(())
+        }
+
+        fn set_state_path(&mut self, path: &Path) {
+            self.state_path = Some(path.to_owned());
+        }
+
+        fn output_added(&mut self, output: &str) {
+            self.added.push(output.to_owned());
+        }
+
+        fn generate_layout(
+            &mut self,
+            _ctx: &LayoutContext<'_>,
+        ) -> Result<GeneratedLayout, Self::Error> {
+            Ok(GeneratedLayout::default())
+        }
+    }
+
+    #[test]
+    fn forwards_lifecycle() {
+        let mut layout = PerOutput::<Test>::new();
+        layout.set_state_path(Path::new("/state/test.json"));
+        layout.on_start().unwrap();
+        layout.output_added("eDP-1");
+        layout.output_added("DP/2");
+
+        let instance = layout.get("eDP-1").unwrap();
+        assert_eq!(
+            instance.state_path.as_deref(),
+            Some(Path::new("/state/test-eDP-1.json"))
+        );
+        assert_eq!(instance.started, 1);
+        assert_eq!(instance.added, ["eDP-1"]);
+        let instance = layout.get("DP/2").unwrap();
+        assert_eq!(
+            instance.state_path.as_deref(),
+            Some(Path::new("/state/test-DP_2.json"))
+        );
+
+        // Started again after reconnecting.
+        layout.on_start().unwrap();
+        assert_eq!(layout.get("eDP-1").unwrap().started, 2);
+    }
+}
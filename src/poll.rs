@@ -0,0 +1,88 @@
+use std::io;
+use std::os::fd::RawFd;
+use std::time::Duration;
+
+/// Block until at least one of `fds` is readable, or until the timeout expires. Returns the
+/// readiness of each fd.
+pub(crate) fn poll_readable<const N: usize>(
+    fds: [RawFd; N],
+    timeout: Option<Duration>,
+) -> io::Result<[bool; N]> {
+    poll(fds.map(|fd| (fd, false)), timeout)
+}
+
+/// Like [`poll_readable`], but fds paired with `true` are also waited on until they are
+/// writable.
+pub(crate) fn poll<const N: usize>(
+    fds: [(RawFd, bool); N],
+    timeout: Option<Duration>,
+) -> io::Result<[bool; N]> {
+    let mut pollfds = fds.map(|(fd, writable)| pollfd(fd, writable));
+    poll_raw(&mut pollfds, timeout)?;
+    // Errors and hangups are reported as readiness, so that the next read surfaces them.
+    Ok(pollfds.map(|p| p.revents != 0))
+}
+
+/// Like [`poll`], but for a number of fds which is only known at runtime. The set is meant to be
+/// cleared and refilled before every call, reusing its allocation.
+#[derive(Default)]
+pub(crate) struct PollSet {
+    fds: Vec<libc::pollfd>,
+}
+
+impl PollSet {
+    pub(crate) fn clear(&mut self) {
+        self.fds.clear();
+    }
+
+    /// Add an fd, which is also waited on until it is writable if `writable` is set. Returns the
+    /// index to pass to [`is_ready`](Self::is_ready).
+    pub(crate) fn push(&mut self, fd: RawFd, writable: bool) -> usize {
+        self.fds.push(pollfd(fd, writable));
+        self.fds.len() - 1
+    }
+
+    pub(crate) fn poll(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        for p in &mut self.fds {
+            p.revents = 0;
+        }
+        poll_raw(&mut self.fds, timeout)
+    }
+
+    pub(crate) fn is_ready(&self, index: usize) -> bool {
+        self.fds[index].revents != 0
+    }
+
+    /// Whether any fd from `index` on is ready.
+    #[cfg_attr(not(feature = "control-socket"), allow(dead_code))]
+    pub(crate) fn any_ready_from(&self, index: usize) -> bool {
+        self.fds[index..].iter().any(|p| p.revents != 0)
+    }
+}
+
+fn pollfd(fd: RawFd, writable: bool) -> libc::pollfd {
+    libc::pollfd {
+        fd,
+        events: if writable {
+            libc::POLLIN | libc::POLLOUT
+        } else {
+            libc::POLLIN
+        },
+        revents: 0,
+    }
+}
+
+fn poll_raw(pollfds: &mut [libc::pollfd], timeout: Option<Duration>) -> io::Result<()> {
+    let timeout = timeout.map_or(-1, |t| t.as_millis().try_into().unwrap_or(libc::c_int::MAX));
+    loop {
+        let res =
+            unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, timeout) };
+        if res >= 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use crate::math;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rectangle {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rectangle {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Move the rectangle to `(x, y)`, keeping its size. Together with [`size`](Self::size) this
+    /// is an alternative to [`new`](Self::new) which does not rely on the order of arguments:
+    ///
+    /// ```
+    /// use river_layout_toolkit::Rectangle;
+    ///
+    /// let rect = Rectangle::default().at(10, 20).size(800, 600);
+    /// assert_eq!(rect, Rectangle::new(10, 20, 800, 600));
+    /// ```
+    #[must_use]
+    pub fn at(self, x: i32, y: i32) -> Self {
+        Self { x, y, ..self }
+    }
+
+    /// Resize the rectangle to `width` by `height`, keeping its position. See [`at`](Self::at).
+    #[must_use]
+    pub fn size(self, width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            ..self
+        }
+    }
+
+    /// Split into a left and a right rectangle. The left one gets `ratio` of the width, rounded
+    /// down, and the right one gets the rest, so the two always cover `self` exactly.
+    ///
+    /// `ratio` is clamped to `[0, 1]`.
+    pub fn split_horizontal(&self, ratio: f64) -> (Rectangle, Rectangle) {
+        let left = split_len(self.width, ratio);
+        (
+            Self::new(self.x, self.y, left, self.height),
+            Self::new(
+                self.x.saturating_add_unsigned(left),
+                self.y,
+                self.width - left,
+                self.height,
+            ),
+        )
+    }
+
+    /// Split into a top and a bottom rectangle. The top one gets `ratio` of the height, rounded
+    /// down, and the bottom one gets the rest, so the two always cover `self` exactly.
+    ///
+    /// `ratio` is clamped to `[0, 1]`.
+    pub fn split_vertical(&self, ratio: f64) -> (Rectangle, Rectangle) {
+        let top = split_len(self.height, ratio);
+        (
+            Self::new(self.x, self.y, self.width, top),
+            Self::new(
+                self.x,
+                self.y.saturating_add_unsigned(top),
+                self.width,
+                self.height - top,
+            ),
+        )
+    }
+
+    /// Split into `n` columns of equal width, from left to right. The remaining pixels go to the
+    /// first columns, see [`math::distribute`], so the columns always cover `self` exactly:
+    ///
+    /// ```
+    /// use river_layout_toolkit::Rectangle;
+    ///
+    /// let columns = Rectangle::new(10, 20, 11, 5).split_columns(3);
+    /// assert_eq!(
+    ///     columns,
+    ///     [
+    ///         Rectangle::new(10, 20, 4, 5),
+    ///         Rectangle::new(14, 20, 4, 5),
+    ///         Rectangle::new(18, 20, 3, 5),
+    ///     ]
+    /// );
+    /// ```
+    ///
+    /// Returns no rectangles if `n` is zero.
+    pub fn split_columns(&self, n: u32) -> Vec<Rectangle> {
+        let mut x = self.x;
+        math::distribute(self.width, n)
+            .map(|width| {
+                let column = Self::new(x, self.y, width, self.height);
+                x = x.saturating_add_unsigned(width);
+                column
+            })
+            .collect()
+    }
+
+    /// Split into `n` rows of equal height, from top to bottom. The remaining pixels go to the
+    /// first rows, like with [`split_columns`](Self::split_columns).
+    ///
+    /// Returns no rectangles if `n` is zero.
+    pub fn split_rows(&self, n: u32) -> Vec<Rectangle> {
+        let mut y = self.y;
+        math::distribute(self.height, n)
+            .map(|height| {
+                let row = Self::new(self.x, y, self.width, height);
+                y = y.saturating_add_unsigned(height);
+                row
+            })
+            .collect()
+    }
+
+    /// Whether the point `(x, y)` lies within this rectangle. The right and bottom edges are
+    /// exclusive.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        let (x, y) = (i64::from(x), i64::from(y));
+        x >= i64::from(self.x) && x < self.right() && y >= i64::from(self.y) && y < self.bottom()
+    }
+
+    /// The overlapping part of two rectangles, or `None` if they do not overlap.
+    pub fn intersection(&self, other: &Rectangle) -> Option<Rectangle> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        (right > i64::from(x) && bottom > i64::from(y)).then(|| Rectangle {
+            x,
+            y,
+            width: (right - i64::from(x)) as u32,
+            height: (bottom - i64::from(y)) as u32,
+        })
+    }
+
+    /// Shrink the rectangle by `amount` pixels on all sides. The width and height are clamped to
+    /// be at least `1`, in which case the rectangle is kept centered.
+    pub fn shrink(&self, amount: u32) -> Rectangle {
+        let width = self.width.saturating_sub(amount.saturating_mul(2)).max(1);
+        let height = self.height.saturating_sub(amount.saturating_mul(2)).max(1);
+        Rectangle {
+            x: self
+                .x
+                .saturating_add_unsigned(self.width.saturating_sub(width) / 2),
+            y: self
+                .y
+                .saturating_add_unsigned(self.height.saturating_sub(height) / 2),
+            width,
+            height,
+        }
+    }
+
+    /// Move and shrink the rectangle so that it lies within `bounds`. The width and height are
+    /// clamped to be at least `1`, even if `bounds` is empty.
+    pub fn clamp_to(&self, bounds: &Rectangle) -> Rectangle {
+        let (x, width) = clamp_span(self.x, self.width, bounds.x, bounds.width);
+        let (y, height) = clamp_span(self.y, self.height, bounds.y, bounds.height);
+        Rectangle {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Round the edges to coordinates which map to whole device pixels on an output with the given
+    /// fractional `scale`, so that the contents of the views are not blurry.
+    ///
+    /// With a scale of `1.5`, for example, only even coordinates map to whole device pixels, so
+    /// every edge is moved to the nearest even coordinate, rounding halfway cases up. Since every
+    /// edge is rounded on its own, edges shared by adjacent rectangles stay shared, and rectangles
+    /// which tile an area still tile it, except at the border of the area if the area itself is
+    /// not aligned. Coordinates are aligned relative to `(0, 0)`.
+    ///
+    /// The rectangle is returned unchanged if it would become empty, or if `scale` is not a
+    /// positive multiple of `1/120`, the precision of fractional scaling in Wayland.
+    pub fn snap_to_scale(&self, scale: f64) -> Rectangle {
+        self.snap_to_scale_within(scale, None)
+    }
+
+    /// Same as [`snap_to_scale`](Self::snap_to_scale), but edges which lie on the border of
+    /// `bounds` are kept in place.
+    pub(crate) fn snap_to_scale_within(&self, scale: f64, bounds: Option<&Rectangle>) -> Rectangle {
+        let Some(step) = scale_step(scale) else {
+            return *self;
+        };
+        let snap = |edge: i64, border: Option<i64>| {
+            if Some(edge) == border {
+                edge
+            } else {
+                (edge + step / 2).div_euclid(step) * step
+            }
+        };
+        let left = snap(i64::from(self.x), bounds.map(|b| i64::from(b.x)));
+        let top = snap(i64::from(self.y), bounds.map(|b| i64::from(b.y)));
+        let right = snap(self.right(), bounds.map(Rectangle::right));
+        let bottom = snap(self.bottom(), bounds.map(Rectangle::bottom));
+        match (i32::try_from(left), i32::try_from(top)) {
+            (Ok(x), Ok(y)) if right > left && bottom > top => Rectangle {
+                x,
+                y,
+                width: u32::try_from(right - left).unwrap_or(u32::MAX),
+                height: u32::try_from(bottom - top).unwrap_or(u32::MAX),
+            },
+            _ => *self,
+        }
+    }
+
+    pub fn area(&self) -> u64 {
+        u64::from(self.width) * u64::from(self.height)
+    }
+
+    /// Whether every edge lies within `bounds`.
+    pub(crate) fn is_within(&self, bounds: &Rectangle) -> bool {
+        self.x >= bounds.x
+            && self.y >= bounds.y
+            && self.right() <= bounds.right()
+            && self.bottom() <= bounds.bottom()
+    }
+
+    fn right(&self) -> i64 {
+        i64::from(self.x) + i64::from(self.width)
+    }
+
+    fn bottom(&self) -> i64 {
+        i64::from(self.y) + i64::from(self.height)
+    }
+}
+
+/// `(x, y, width, height)`
+impl From<(i32, i32, u32, u32)> for Rectangle {
+    fn from((x, y, width, height): (i32, i32, u32, u32)) -> Self {
+        Self::new(x, y, width, height)
+    }
+}
+
+/// `(x, y, width, height)`
+impl From<Rectangle> for (i32, i32, u32, u32) {
+    fn from(rect: Rectangle) -> Self {
+        (rect.x, rect.y, rect.width, rect.height)
+    }
+}
+
+fn split_len(len: u32, ratio: f64) -> u32 {
+    math::scale(len, ratio.clamp(0.0, 1.0))
+}
+
+/// The smallest number of logical pixels which is a whole number of device pixels at `scale`.
+fn scale_step(scale: f64) -> Option<i64> {
+    // Allow for scales given with a limited number of decimals, like `1.3333`.
+    let is_whole = |value: f64| (value - value.round()).abs() < 1e-2;
+    if !(scale.is_finite() && scale > 0.0 && is_whole(scale * 120.0)) {
+        return None;
+    }
+    (1..=120).find(|&step| is_whole(step as f64 * scale))
+}
+
+fn clamp_span(start: i32, len: u32, bound_start: i32, bound_len: u32) -> (i32, u32) {
+    let bound_end = i64::from(bound_start) + i64::from(bound_len);
+    let start = i64::from(start)
+        .min(bound_end - 1)
+        .max(i64::from(bound_start));
+    let end = (start + i64::from(len)).min(bound_end);
+    (start as i32, (end - start).max(1) as u32)
+}
+
+/// A rectangle with fractional coordinates, for computing layouts with exact proportions. Convert
+/// a whole layout to pixels with [`math::to_rectangle_grid`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FloatRect {
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+impl FloatRect {
+    pub fn new(x: f64, y: f64, w: f64, h: f64) -> Self {
+        Self { x, y, w, h }
+    }
+
+    /// Split into a left and a right rectangle, the left one getting `ratio` of the width.
+    ///
+    /// `ratio` is clamped to `[0, 1]`.
+    pub fn split_horizontal(&self, ratio: f64) -> (FloatRect, FloatRect) {
+        let left = self.w * ratio.clamp(0.0, 1.0);
+        (
+            Self::new(self.x, self.y, left, self.h),
+            Self::new(self.x + left, self.y, self.w - left, self.h),
+        )
+    }
+
+    /// Split into a top and a bottom rectangle, the top one getting `ratio` of the height.
+    ///
+    /// `ratio` is clamped to `[0, 1]`.
+    pub fn split_vertical(&self, ratio: f64) -> (FloatRect, FloatRect) {
+        let top = self.h * ratio.clamp(0.0, 1.0);
+        (
+            Self::new(self.x, self.y, self.w, top),
+            Self::new(self.x, self.y + top, self.w, self.h - top),
+        )
+    }
+}
+
+impl From<Rectangle> for FloatRect {
+    fn from(rect: Rectangle) -> Self {
+        Self::new(
+            f64::from(rect.x),
+            f64::from(rect.y),
+            f64::from(rect.width),
+            f64::from(rect.height),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_odd_sizes() {
+        let rect = Rectangle::new(10, 20, 101, 7);
+        assert_eq!(
+            rect.split_horizontal(0.5),
+            (Rectangle::new(10, 20, 50, 7), Rectangle::new(60, 20, 51, 7)),
+        );
+        assert_eq!(
+            rect.split_vertical(0.5),
+            (
+                Rectangle::new(10, 20, 101, 3),
+                Rectangle::new(10, 23, 101, 4)
+            ),
+        );
+        // Rounded down, so that the first rectangle never gets more than its share.
+        assert_eq!(rect.split_horizontal(1.0 / 3.0).0.width, 33);
+        assert_eq!(rect.split_vertical(0.99).0.height, 6);
+    }
+
+    #[test]
+    fn split_covers_rectangle() {
+        for size in [1, 2, 3, 7, 100, 101, 1919, u32::MAX] {
+            for ratio in [
+                -1.0,
+                0.0,
+                0.05,
+                1.0 / 3.0,
+                0.5,
+                0.6,
+                0.95,
+                1.0,
+                2.0,
+                f64::NAN,
+            ] {
+                let rect = Rectangle::new(-5, 3, size, size);
+                let (left, right) = rect.split_horizontal(ratio);
+                assert_eq!(left.width + right.width, size, "{size} {ratio}");
+                assert_eq!(i64::from(right.x), left.right().min(i64::from(i32::MAX)));
+                let (top, bottom) = rect.split_vertical(ratio);
+                assert_eq!(top.height + bottom.height, size, "{size} {ratio}");
+                assert_eq!(i64::from(bottom.y), top.bottom().min(i64::from(i32::MAX)));
+            }
+        }
+    }
+
+    #[test]
+    fn split_columns_and_rows_rebuild_rectangle() {
+        for size in [0, 1, 2, 5, 11, 100, 1080, 1919] {
+            for n in 1..=12 {
+                let rect = Rectangle::new(-7, 30, size, size + 1);
+                let columns = rect.split_columns(n);
+                assert_eq!(columns.len(), n as usize);
+                let mut x = rect.x;
+                for column in &columns {
+                    assert_eq!(
+                        (column.x, column.y, column.height),
+                        (x, rect.y, rect.height)
+                    );
+                    x += column.width as i32;
+                }
+                assert_eq!(i64::from(x), rect.right(), "{size} {n}");
+
+                let rows = rect.split_rows(n);
+                assert_eq!(rows.len(), n as usize);
+                let mut y = rect.y;
+                for row in &rows {
+                    assert_eq!((row.x, row.y, row.width), (rect.x, y, rect.width));
+                    y += row.height as i32;
+                }
+                assert_eq!(i64::from(y), rect.bottom(), "{size} {n}");
+            }
+        }
+        assert!(Rectangle::new(0, 0, 10, 10).split_columns(0).is_empty());
+        assert!(Rectangle::new(0, 0, 10, 10).split_rows(0).is_empty());
+    }
+}
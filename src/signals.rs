@@ -0,0 +1,45 @@
+use std::io;
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::low_level::{self, pipe};
+use signal_hook::SigId;
+
+/// A socket which becomes readable once SIGINT or SIGTERM is received.
+pub(crate) struct SignalPipe {
+    read: UnixStream,
+    // Closed only after the handlers are unregistered.
+    write: UnixStream,
+    ids: Vec<SigId>,
+}
+
+impl SignalPipe {
+    pub(crate) fn install() -> io::Result<Self> {
+        let (read, write) = UnixStream::pair()?;
+        let mut this = Self {
+            read,
+            write,
+            ids: Vec::new(),
+        };
+        for signal in [SIGINT, SIGTERM] {
+            let id = pipe::register_raw(signal, this.write.as_raw_fd())?;
+            this.ids.push(id);
+        }
+        Ok(this)
+    }
+}
+
+impl AsRawFd for SignalPipe {
+    fn as_raw_fd(&self) -> RawFd {
+        self.read.as_raw_fd()
+    }
+}
+
+impl Drop for SignalPipe {
+    fn drop(&mut self) {
+        for id in self.ids.drain(..) {
+            low_level::unregister(id);
+        }
+    }
+}
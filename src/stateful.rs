@@ -0,0 +1,282 @@
+use std::path::{Path, PathBuf};
+
+use crate::command::Command;
+use crate::{GeneratedLayout, Layout, LayoutContext, StateStore, Tags, UserCmdOutcome};
+
+/// A simpler kind of layout, which only computes the geometry. The standard commands are handled
+/// by the [`Stateful`] wrapper, which implements [`Layout`].
+///
+/// The supported commands are `main-count`, `main-ratio` and `gaps`, each taking either an absolute
+/// value (`main-ratio 0.6`) or a relative one (`main-count +1`, `main-ratio -0.05`), `reset`,
+/// which restores the initial state of all tags of the output, and `reset-tag`, which only
+/// restores the initial state of the focused tags.
+///
+/// The state is kept per output and per tag: the lowest focused tag, so that viewing several tags
+/// at once uses the state of one of them. With version 1 of the protocol, where commands come
+/// without tags, the state is kept per output.
+///
+/// With the `persist` feature, the state is loaded on start, see [`Layout::set_state_path`], and
+/// saved whenever the layout generator stops, whether gracefully or with an error, see
+/// [`Layout::on_shutdown`].
+pub trait StatefulLayout: 'static {
+    /// See [`Layout::NAMESPACE`].
+    const NAMESPACE: &'static str;
+
+    /// The state of an output which has not received any commands yet, unless overridden with
+    /// [`Stateful::with_initial_state`].
+    fn initial_state(&self) -> LayoutState {
+        LayoutState::default()
+    }
+
+    /// Only apply the gaps with enough views, see [`SmartGaps`]. When there are fewer views,
+    /// [`arrange`](Self::arrange) receives a state with `gaps` set to `0`.
+    fn smart_gaps(&self) -> Option<SmartGaps> {
+        None
+    }
+
+    /// Generate the layout for the current state of the output.
+    fn arrange(&mut self, state: &LayoutState, ctx: &LayoutContext<'_>) -> GeneratedLayout;
+}
+
+/// The per-output state managed by [`Stateful`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LayoutState {
+    /// The number of views in the main area, at least `1`.
+    pub main_count: u32,
+    /// The fraction of the usable area taken by the main area, between `0.05` and `0.95`.
+    pub main_ratio: f64,
+    /// The gap between views, in pixels.
+    pub gaps: u32,
+}
+
+impl Default for LayoutState {
+    fn default() -> Self {
+        Self {
+            main_count: 1,
+            main_ratio: 0.6,
+            gaps: 0,
+        }
+    }
+}
+
+/// Gaps which are only applied when there are at least `min_views` views, so that a single view
+/// covers the whole usable area:
+///
+/// ```
+/// use river_layout_toolkit::{GeneratedLayout, Rectangle, SmartGaps};
+///
+/// let usable = Rectangle::new(0, 0, 1920, 1080);
+/// let gap = SmartGaps::default().gap(10, 1);
+/// let layout = GeneratedLayout::builder("[]").push_with_gap(usable, gap).build();
+/// assert_eq!(layout.views, [usable]);
+/// assert_eq!(SmartGaps::default().gap(10, 2), 10);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SmartGaps {
+    /// The number of views from which on the gaps are applied, `2` by default.
+    pub min_views: u32,
+}
+
+impl Default for SmartGaps {
+    fn default() -> Self {
+        Self { min_views: 2 }
+    }
+}
+
+impl SmartGaps {
+    /// The gap to use with `view_count` views: `gaps`, or `0` with fewer than `min_views` views.
+    pub fn gap(&self, gaps: u32, view_count: u32) -> u32 {
+        if view_count >= self.min_views {
+            gaps
+        } else {
+            0
+        }
+    }
+}
+
+/// The error returned for commands not understood by [`Stateful`].
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid command: {0:?}")]
+pub struct InvalidCommand(pub String);
+
+/// Turns a [`StatefulLayout`] into a [`Layout`].
+#[derive(Debug)]
+pub struct Stateful<T> {
+    inner: T,
+    initial_state: Option<LayoutState>,
+    states: StateStore<LayoutState>,
+    state_path: Option<PathBuf>,
+}
+
+impl<T: StatefulLayout> Stateful<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            initial_state: None,
+            states: StateStore::new(),
+            state_path: None,
+        }
+    }
+
+    /// Use `state` for outputs which have not received any commands yet and after `reset`,
+    /// instead of [`StatefulLayout::initial_state`].
+    #[must_use]
+    pub fn with_initial_state(mut self, state: LayoutState) -> Self {
+        self.initial_state = Some(state);
+        self
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// The state used for the given output when `tags` are focused, if it has received any
+    /// commands for them. Pass `None` for the state used with version 1 of the protocol.
+    pub fn state(&self, output: &str, tags: Option<Tags>) -> Option<&LayoutState> {
+        [tags.and_then(key), None]
+            .into_iter()
+            .find_map(|key| self.states.get(output, key))
+    }
+
+    fn initial_state(&self) -> LayoutState {
+        self.initial_state
+            .unwrap_or_else(|| self.inner.initial_state())
+    }
+}
+
+/// The key of the state used when `tags` are focused, see [`StatefulLayout`].
+fn key(tags: Tags) -> Option<Tags> {
+    tags.primary().map(|index| Tags(1 << index))
+}
+
+impl<T: StatefulLayout> Layout for Stateful<T> {
+    type Error = InvalidCommand;
+
+    const NAMESPACE: &'static str = T::NAMESPACE;
+
+    fn user_cmd(
+        &mut self,
+        cmd: String,
+        tags: Option<Tags>,
+        output: &str,
+    ) -> Result<UserCmdOutcome, Self::Error> {
+        let command = match Command::parse(&cmd) {
+            Ok(Command::Other(_)) | Err(_) => return Err(InvalidCommand(cmd)),
+            Ok(command) => command,
+        };
+        let key = tags.and_then(key);
+        // Forgetting the state makes the next layout demand use the initial state again.
+        match command {
+            Command::Reset => {
+                self.states.remove_output(output);
+                return Ok(UserCmdOutcome::RequestLayout);
+            }
+            Command::ResetTag => {
+                self.states.remove(output, key);
+                return Ok(UserCmdOutcome::RequestLayout);
+            }
+            _ => (),
+        }
+        let initial_state = self.initial_state();
+        let state = self
+            .states
+            .get_or_insert_with(output, key, || initial_state);
+        match command {
+            Command::MainCount(value) => {
+                let count = value.apply(i64::from(state.main_count));
+                state.main_count = count.clamp(1, i64::from(u32::MAX)) as u32;
+            }
+            Command::MainRatio(value) => {
+                state.main_ratio = value.apply(state.main_ratio).clamp(0.05, 0.95);
+            }
+            Command::Gaps(value) => {
+                let gaps = value.apply(i64::from(state.gaps));
+                state.gaps = gaps.clamp(0, i64::from(u32::MAX)) as u32;
+            }
+            Command::Reset | Command::ResetTag | Command::Other(_) => unreachable!(),
+        }
+        Ok(UserCmdOutcome::RequestLayout)
+    }
+
+    fn set_state_path(&mut self, path: &Path) {
+        self.state_path = Some(path.to_owned());
+    }
+
+    #[cfg(feature = "persist")]
+    fn on_start(&mut self) -> Result<(), Self::Error> {
+        if let Some(path) = &self.state_path {
+            self.states = StateStore::load(path);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "persist")]
+    fn on_shutdown(&mut self) {
+        if let Some(path) = &self.state_path {
+            if let Err(e) = self.states.save(path) {
+                log::warn!("could not save state to {}: {e}", path.display());
+            }
+        }
+    }
+
+    fn generate_layout(&mut self, ctx: &LayoutContext<'_>) -> Result<GeneratedLayout, Self::Error> {
+        let mut state = self
+            .state(&ctx.output.name, Some(ctx.tags))
+            .copied()
+            .unwrap_or_else(|| self.initial_state());
+        if let Some(smart_gaps) = self.inner.smart_gaps() {
+            state.gaps = smart_gaps.gap(state.gaps, ctx.view_count);
+        }
+        Ok(self.inner.arrange(&state, ctx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Test;
+
+    impl StatefulLayout for Test {
+        const NAMESPACE: &'static str = "test";
+
+        fn arrange(&mut self, _state: &LayoutState, _ctx: &LayoutContext<'_>) -> GeneratedLayout {
+            GeneratedLayout::default()
+        }
+    }
+
+    fn main_count(layout: &Stateful<Test>, tags: Tags) -> Option<u32> {
+        layout.state("eDP-1", Some(tags)).map(|s| s.main_count)
+    }
+
+    #[test]
+    fn state_per_tag() {
+        let mut layout = Stateful::new(Test);
+        let cmd = |layout: &mut Stateful<Test>, cmd: &str, tags| {
+            let outcome = layout.user_cmd(cmd.to_owned(), tags, "eDP-1").unwrap();
+            assert_eq!(outcome, UserCmdOutcome::RequestLayout);
+        };
+        cmd(&mut layout, "main-count 2", Some(Tags(0b01)));
+        cmd(&mut layout, "main-count 3", Some(Tags(0b10)));
+        cmd(&mut layout, "main-count 4", None);
+        assert_eq!(main_count(&layout, Tags(0b01)), Some(2));
+        // Viewing several tags uses the state of the lowest one.
+        assert_eq!(main_count(&layout, Tags(0b11)), Some(2));
+        assert_eq!(main_count(&layout, Tags(0b10)), Some(3));
+        // Tags without state of their own fall back to the state of the output.
+        assert_eq!(main_count(&layout, Tags(0b100)), Some(4));
+
+        cmd(&mut layout, "reset-tag", Some(Tags(0b01)));
+        assert_eq!(main_count(&layout, Tags(0b01)), Some(4));
+        assert_eq!(main_count(&layout, Tags(0b10)), Some(3));
+
+        cmd(&mut layout, "reset", Some(Tags(0b01)));
+        assert_eq!(main_count(&layout, Tags(0b10)), None);
+        assert_eq!(layout.state("eDP-1", None), None);
+    }
+}
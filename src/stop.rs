@@ -0,0 +1,45 @@
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+
+/// Stops a [`LayoutRunner`](crate::LayoutRunner) from another thread, see
+/// [`LayoutRunner::stop_handle`](crate::LayoutRunner::stop_handle). Cloning it gives another
+/// handle to the same runner.
+#[derive(Clone)]
+pub struct StopHandle {
+    pipe: Arc<StopPipe>,
+}
+
+/// Both ends are kept alive by every handle, so writing never fails because the runner is gone.
+struct StopPipe {
+    read: UnixStream,
+    write: UnixStream,
+}
+
+impl StopHandle {
+    pub(crate) fn new() -> io::Result<Self> {
+        let (read, write) = UnixStream::pair()?;
+        read.set_nonblocking(true)?;
+        write.set_nonblocking(true)?;
+        Ok(Self {
+            pipe: Arc::new(StopPipe { read, write }),
+        })
+    }
+
+    /// Ask the runner to stop: the protocol objects are destroyed, [`Layout::on_shutdown`] is
+    /// called and [`LayoutRunner::run`] returns `Ok(())`. Does nothing if the runner has already
+    /// stopped.
+    ///
+    /// [`Layout::on_shutdown`]: crate::Layout::on_shutdown
+    /// [`LayoutRunner::run`]: crate::LayoutRunner::run
+    pub fn stop(&self) {
+        // A full buffer means that a stop is already pending.
+        let _ = (&self.pipe.write).write(&[0]);
+    }
+
+    /// The file descriptor which becomes readable once [`stop`](Self::stop) is called.
+    pub(crate) fn fd(&self) -> RawFd {
+        self.pipe.read.as_raw_fd()
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+
+use crate::Tags;
+
+/// Layout state keyed by output name and tags.
+///
+/// With the `persist` feature, the store can be saved to and loaded from a JSON file. See
+/// [`Layout::set_state_path`](crate::Layout::set_state_path) for where that file lives.
+#[derive(Debug, Clone)]
+pub struct StateStore<T> {
+    entries: HashMap<String, HashMap<Option<Tags>, T>>,
+}
+
+impl<T> Default for StateStore<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> StateStore<T> {
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Use `None` as `tags` for state which is shared by all tags of an output.
+    pub fn get(&self, output: &str, tags: Option<Tags>) -> Option<&T> {
+        self.entries.get(output)?.get(&tags)
+    }
+
+    pub fn get_or_insert_with(
+        &mut self,
+        output: &str,
+        tags: Option<Tags>,
+        f: impl FnOnce() -> T,
+    ) -> &mut T {
+        if !self.entries.contains_key(output) {
+            self.entries.insert(output.to_owned(), HashMap::new());
+        }
+        self.entries
+            .get_mut(output)
+            .unwrap()
+            .entry(tags)
+            .or_insert_with(f)
+    }
+
+    pub fn insert(&mut self, output: &str, tags: Option<Tags>, value: T) -> Option<T> {
+        self.entries
+            .entry(output.to_owned())
+            .or_default()
+            .insert(tags, value)
+    }
+
+    /// Forget the state of an output for the given tags.
+    pub fn remove(&mut self, output: &str, tags: Option<Tags>) -> Option<T> {
+        self.entries.get_mut(output)?.remove(&tags)
+    }
+
+    /// Forget all state of an output.
+    pub fn remove_output(&mut self, output: &str) {
+        self.entries.remove(output);
+    }
+}
+
+#[cfg(feature = "persist")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Entry<T> {
+    output: String,
+    tags: Option<Tags>,
+    state: T,
+}
+
+#[cfg(feature = "persist")]
+impl<T: serde::Serialize + serde::de::DeserializeOwned> StateStore<T> {
+    /// Load the store from a file. A missing or corrupt file results in an empty store, the latter
+    /// with a warning.
+    pub fn load(path: &std::path::Path) -> Self {
+        let mut store = Self::new();
+        let data = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return store,
+            Err(e) => {
+                log::warn!("could not read state from {}: {e}", path.display());
+                return store;
+            }
+        };
+        match serde_json::from_slice::<Vec<Entry<T>>>(&data) {
+            Ok(entries) => {
+                for entry in entries {
+                    store.insert(&entry.output, entry.tags, entry.state);
+                }
+            }
+            Err(e) => log::warn!("ignoring corrupt state in {}: {e}", path.display()),
+        }
+        store
+    }
+
+    /// Save the store to a file, creating the parent directories if needed.
+    pub fn save(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let entries: Vec<_> = self
+            .entries
+            .iter()
+            .flat_map(|(output, states)| {
+                states.iter().map(|(tags, state)| Entry {
+                    output: output.clone(),
+                    tags: *tags,
+                    state,
+                })
+            })
+            .collect();
+        let data = serde_json::to_vec_pretty(&entries)?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // Write to a temporary file first, so that a crash never leaves a truncated file behind.
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(tmp, path)
+    }
+}
+
+/// The directory for persisted layout state: `$XDG_STATE_HOME/river-layout-toolkit`, falling back
+/// to `~/.local/state/river-layout-toolkit`.
+pub fn default_state_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(base.join("river-layout-toolkit"))
+}
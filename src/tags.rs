@@ -0,0 +1,62 @@
+/// A set of tags, backed by a 32-bit bitfield.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Tags(pub u32);
+
+impl Tags {
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Iterate over the indices of the set tags, in ascending order.
+    pub fn iter_set(self) -> impl Iterator<Item = u32> {
+        (0..u32::BITS).filter(move |&i| self.contains(i))
+    }
+
+    /// The indices of the set tags, in ascending order.
+    pub fn as_index_vec(self) -> Vec<u32> {
+        self.iter_set().collect()
+    }
+
+    /// The index of the lowest set tag, i.e. of the lowest set bit, if any.
+    ///
+    /// River can focus several tags at once, so there is no single focused tag. By convention,
+    /// per-tag settings are looked up for the lowest focused tag:
+    ///
+    /// ```
+    /// use river_layout_toolkit::Tags;
+    ///
+    /// assert_eq!(Tags(0b0110).primary(), Some(1));
+    /// assert_eq!(Tags(0).primary(), None);
+    /// ```
+    pub fn primary(self) -> Option<u32> {
+        (!self.is_empty()).then(|| self.0.trailing_zeros())
+    }
+
+    /// The number of set tags.
+    pub fn count(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    /// Whether the tag with the given index is set. Indices start at zero.
+    pub fn contains(self, index: u32) -> bool {
+        index < u32::BITS && self.0 & (1 << index) != 0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl From<u32> for Tags {
+    fn from(bits: u32) -> Self {
+        Self(bits)
+    }
+}
+
+impl From<Tags> for u32 {
+    fn from(tags: Tags) -> Self {
+        tags.0
+    }
+}
@@ -0,0 +1,262 @@
+//! Helpers for testing [`Layout`] implementations without a running compositor.
+
+use crate::{
+    check_overlap, check_view_count, Demand, Error, GeneratedLayout, Layout, LayoutContext,
+    OutputInfo, Outputs, Tags, UserCmdOutcome, ViewStream,
+};
+
+/// The parameters of a simulated layout demand.
+#[derive(Debug, Clone)]
+pub struct DemandInput {
+    pub view_count: u32,
+    pub usable_width: u32,
+    pub usable_height: u32,
+    pub tags: Tags,
+    pub output: OutputInfo,
+    /// All outputs returned by [`LayoutContext::outputs`]. Defaults to just `output`.
+    pub outputs: Vec<OutputInfo>,
+    /// Returned by [`LayoutContext::previous_layout`]. Defaults to `None`.
+    pub previous_layout: Option<GeneratedLayout>,
+    /// Returned by [`LayoutContext::previous_tags`]. Defaults to `None`, as for the first demand.
+    pub previous_tags: Option<Tags>,
+    /// Defaults to `2`, the latest version.
+    pub protocol_version: u32,
+    /// Defaults to `0`.
+    pub serial: u32,
+    /// Whether [`drive_layout`] should check the number of generated views, like
+    /// [`run`](crate::run) does. Defaults to `true`.
+    pub check_view_count: bool,
+    /// Whether [`drive_layout`] should fail if any two views overlap, like
+    /// [`RunOptions::detect_overlap`](crate::RunOptions::detect_overlap). Defaults to `false`.
+    pub detect_overlap: bool,
+}
+
+impl DemandInput {
+    /// Create a demand for the first tag of an output named `test`.
+    pub fn new(view_count: u32, usable_width: u32, usable_height: u32) -> Self {
+        Self {
+            view_count,
+            usable_width,
+            usable_height,
+            tags: Tags(1),
+            output: OutputInfo::new("test"),
+            outputs: vec![OutputInfo::new("test")],
+            previous_layout: None,
+            previous_tags: None,
+            protocol_version: 2,
+            serial: 0,
+            check_view_count: true,
+            detect_overlap: false,
+        }
+    }
+
+    #[must_use]
+    pub fn tags(mut self, tags: impl Into<Tags>) -> Self {
+        self.tags = tags.into();
+        self
+    }
+
+    /// Set the output of the demand. This also replaces it in [`outputs`](Self::outputs).
+    #[must_use]
+    pub fn output(mut self, output: OutputInfo) -> Self {
+        self.outputs.retain(|o| o.name != self.output.name);
+        self.outputs.push(output.clone());
+        self.output = output;
+        self
+    }
+
+    #[must_use]
+    pub fn previous_layout(mut self, layout: GeneratedLayout) -> Self {
+        self.previous_layout = Some(layout);
+        self
+    }
+
+    /// Add another connected output, as seen by [`LayoutContext::outputs`].
+    #[must_use]
+    pub fn other_output(mut self, output: OutputInfo) -> Self {
+        self.outputs.push(output);
+        self
+    }
+
+    #[must_use]
+    pub fn check_view_count(mut self, check: bool) -> Self {
+        self.check_view_count = check;
+        self
+    }
+
+    #[must_use]
+    pub fn detect_overlap(mut self, detect: bool) -> Self {
+        self.detect_overlap = detect;
+        self
+    }
+}
+
+/// Call [`Layout::generate_layout_into`] (and thus [`Layout::generate_layout`], unless it is
+/// overridden) as if the compositor has sent a layout demand. Layouts which implement
+/// [`Layout::stream_layout`] are streamed instead, collecting all pushed views.
+///
+/// # Errors
+///
+/// Returns [`Error::LayoutError`] if the layout fails and [`Error::InvalidGeneratedLayout`] (or
+/// [`Error::ViewCountMismatch`] for streamed layouts) if the number of views is wrong (unless
+/// [`DemandInput::check_view_count`] is disabled). Returns
+/// [`Error::OverlappingViews`] if [`DemandInput::detect_overlap`] is enabled and views overlap,
+/// and [`Error::StreamAbandoned`] if the layout pushes views and then declines to stream.
+pub fn drive_layout<L: Layout>(
+    layout: &mut L,
+    input: &DemandInput,
+) -> Result<GeneratedLayout, Error<L::Error>> {
+    let ctx = LayoutContext {
+        view_count: input.view_count,
+        usable_width: input.usable_width,
+        usable_height: input.usable_height,
+        tags: input.tags,
+        output: &input.output,
+        protocol_version: input.protocol_version,
+        serial: input.serial,
+        outputs: Outputs::List(&input.outputs),
+        previous_layout: input.previous_layout.as_ref(),
+        previous_tags: input.previous_tags,
+    };
+    let mut generated_layout = GeneratedLayout {
+        layout_name: String::new(),
+        views: Vec::new(),
+    };
+    let mut push = |_, view| generated_layout.views.push(view);
+    let mut stream = ViewStream::new(ctx, &mut push);
+    match layout.stream_layout(&mut stream) {
+        Some(result) => {
+            result.map_err(Error::LayoutError)?;
+            let pushed = stream.pushed;
+            generated_layout.layout_name = stream.layout_name;
+            if input.check_view_count && pushed != input.view_count as usize {
+                return Err(Error::ViewCountMismatch {
+                    expected: input.view_count,
+                    pushed,
+                    output: input.output.name.clone(),
+                });
+            }
+        }
+        None if stream.pushed > 0 => {
+            return Err(Error::StreamAbandoned {
+                pushed: stream.pushed,
+                output: input.output.name.clone(),
+            });
+        }
+        None => {
+            let mut demand = Demand::new(
+                ctx,
+                &mut generated_layout.views,
+                &mut generated_layout.layout_name,
+            );
+            layout
+                .generate_layout_into(&mut demand)
+                .map_err(Error::LayoutError)?;
+        }
+    }
+    if input.check_view_count {
+        check_view_count(
+            &generated_layout.views,
+            input.view_count,
+            &input.output.name,
+        )?;
+    }
+    if input.detect_overlap {
+        check_overlap(&generated_layout.views, &input.output.name)?;
+    }
+    Ok(generated_layout)
+}
+
+/// Call [`Layout::user_cmd`] for each command in order, as if they were sent via
+/// `riverctl send-layout-cmd`.
+///
+/// # Errors
+///
+/// Stops at and returns the first error. On success, returns the outcome of each command.
+pub fn feed_user_cmds<'a, L: Layout>(
+    layout: &mut L,
+    cmds: impl IntoIterator<Item = &'a str>,
+    tags: Option<Tags>,
+    output: &str,
+) -> Result<Vec<UserCmdOutcome>, L::Error> {
+    cmds.into_iter()
+        .map(|cmd| layout.user_cmd(cmd.to_owned(), tags, output))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+    use crate::Rectangle;
+
+    /// Streams `pushed` views, then returns `result`.
+    struct Streaming {
+        pushed: u32,
+        result: Option<Result<(), Infallible>>,
+    }
+
+    impl Layout for Streaming {
+        type Error = Infallible;
+
+        const NAMESPACE: &'static str = "streaming";
+
+        fn stream_layout(&mut self, stream: &mut ViewStream<'_>) -> Option<Result<(), Infallible>> {
+            for i in 0..self.pushed {
+                stream.push(Rectangle::new(i as i32 * 10, 0, 10, 10));
+            }
+            self.result
+        }
+
+        fn generate_layout(
+            &mut self,
+            ctx: &LayoutContext<'_>,
+        ) -> Result<GeneratedLayout, Infallible> {
+            let views = vec![Rectangle::new(0, 0, 10, 10); ctx.view_count as usize];
+            Ok(GeneratedLayout::from_views("fallback", views))
+        }
+    }
+
+    #[test]
+    fn abandoned_stream() {
+        let mut layout = Streaming {
+            pushed: 2,
+            result: None,
+        };
+        let err = drive_layout(&mut layout, &DemandInput::new(3, 100, 100)).unwrap_err();
+        assert!(
+            matches!(err, Error::StreamAbandoned { pushed: 2, .. }),
+            "{err}"
+        );
+
+        // Declining without pushing anything falls back to `generate_layout`.
+        layout.pushed = 0;
+        let generated = drive_layout(&mut layout, &DemandInput::new(3, 100, 100)).unwrap();
+        assert_eq!(generated.layout_name, "fallback");
+    }
+
+    #[test]
+    fn too_few_streamed_views() {
+        let mut layout = Streaming {
+            pushed: 2,
+            result: Some(Ok(())),
+        };
+        let err = drive_layout(&mut layout, &DemandInput::new(3, 100, 100)).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::ViewCountMismatch {
+                    expected: 3,
+                    pushed: 2,
+                    ..
+                }
+            ),
+            "{err}"
+        );
+
+        let mut input = DemandInput::new(3, 100, 100);
+        input.check_view_count = false;
+        assert_eq!(drive_layout(&mut layout, &input).unwrap().views.len(), 2);
+    }
+}
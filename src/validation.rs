@@ -0,0 +1,144 @@
+use crate::{GeneratedLayout, Rectangle};
+
+/// The checks [`GeneratedLayout::validate`] performs in addition to the view count. All of them
+/// are disabled by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ValidationOptions {
+    /// Every view has to lie within the usable area, see also [`RunOptions::clamp_to_usable`].
+    ///
+    /// [`RunOptions::clamp_to_usable`]: crate::RunOptions::clamp_to_usable
+    pub bounds: bool,
+    /// Every view has to have a non-zero width and height, see also
+    /// [`RunOptions::on_zero_size_view`].
+    ///
+    /// [`RunOptions::on_zero_size_view`]: crate::RunOptions::on_zero_size_view
+    pub zero_size: bool,
+    /// No two views may overlap, see also [`RunOptions::detect_overlap`]. This is quadratic in the
+    /// number of views.
+    ///
+    /// [`RunOptions::detect_overlap`]: crate::RunOptions::detect_overlap
+    pub overlap: bool,
+}
+
+/// The first problem found by [`GeneratedLayout::validate`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ValidationError {
+    #[error("expected {expected} views, got {got}")]
+    ViewCount { expected: u32, got: usize },
+    #[error("view {index} is out of bounds: {view:?}")]
+    OutOfBounds { index: usize, view: Rectangle },
+    #[error("view {index} has a zero width or height")]
+    ZeroSize { index: usize },
+    #[error("views {first} and {second} overlap")]
+    Overlap { first: usize, second: usize },
+}
+
+impl GeneratedLayout {
+    /// Check the layout the way [`run`](crate::run) does before sending it to the compositor,
+    /// e.g. in the tests of a layout. Returns the first problem found:
+    ///
+    /// ```
+    /// use river_layout_toolkit::{GeneratedLayout, Rectangle, ValidationError, ValidationOptions};
+    ///
+    /// let usable = Rectangle::new(0, 0, 1920, 1080);
+    /// let (left, right) = usable.split_horizontal(0.5);
+    /// let layout = GeneratedLayout::from_views("[]=", [left, right, right]);
+    ///
+    /// let options = ValidationOptions {
+    ///     overlap: true,
+    ///     ..Default::default()
+    /// };
+    /// let count = ValidationError::ViewCount { expected: 2, got: 3 };
+    /// assert_eq!(layout.validate(2, usable, options), Err(count));
+    /// let overlap = ValidationError::Overlap { first: 1, second: 2 };
+    /// assert_eq!(layout.validate(3, usable, options), Err(overlap));
+    /// assert_eq!(layout.validate(3, usable, ValidationOptions::default()), Ok(()));
+    /// ```
+    pub fn validate(
+        &self,
+        view_count: u32,
+        usable: Rectangle,
+        options: ValidationOptions,
+    ) -> Result<(), ValidationError> {
+        if !self.has_view_count(view_count) {
+            return Err(ValidationError::ViewCount {
+                expected: view_count,
+                got: self.views.len(),
+            });
+        }
+        for (index, view) in self.views.iter().enumerate() {
+            if options.zero_size && (view.width == 0 || view.height == 0) {
+                return Err(ValidationError::ZeroSize { index });
+            }
+            if options.bounds && !view.is_within(&usable) {
+                return Err(ValidationError::OutOfBounds { index, view: *view });
+            }
+        }
+        if options.overlap {
+            if let Some((first, second)) = overlaps(&self.views).next() {
+                return Err(ValidationError::Overlap { first, second });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The pairs of indices of overlapping views, each pair in ascending order.
+pub(crate) fn overlaps(views: &[Rectangle]) -> impl Iterator<Item = (usize, usize)> + '_ {
+    views.iter().enumerate().flat_map(move |(i, a)| {
+        views
+            .iter()
+            .enumerate()
+            .skip(i + 1)
+            .filter(move |(_, b)| a.intersection(b).is_some())
+            .map(move |(j, _)| (i, j))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_size_within_bounds() {
+        let usable = Rectangle::new(0, 0, 100, 100);
+        let layout = GeneratedLayout::from_views("", [Rectangle::new(10, 10, 0, 50)]);
+        let options = ValidationOptions {
+            bounds: true,
+            zero_size: true,
+            overlap: false,
+        };
+        let zero_size = ValidationError::ZeroSize { index: 0 };
+        assert_eq!(layout.validate(1, usable, options), Err(zero_size));
+        let bounds_only = ValidationOptions {
+            zero_size: false,
+            ..options
+        };
+        assert_eq!(layout.validate(1, usable, bounds_only), Ok(()));
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        let usable = Rectangle::new(10, 10, 100, 100);
+        let options = ValidationOptions {
+            bounds: true,
+            ..Default::default()
+        };
+        let inside = [
+            Rectangle::new(10, 10, 100, 100),
+            Rectangle::new(50, 50, 60, 60),
+        ];
+        let layout = GeneratedLayout::from_views("", inside);
+        assert_eq!(layout.validate(2, usable, options), Ok(()));
+        for view in [
+            Rectangle::new(9, 10, 10, 10),
+            Rectangle::new(10, 9, 10, 10),
+            Rectangle::new(101, 10, 10, 10),
+            Rectangle::new(10, 101, 10, 10),
+        ] {
+            let layout = GeneratedLayout::from_views("", [view]);
+            let err = ValidationError::OutOfBounds { index: 0, view };
+            assert_eq!(layout.validate(1, usable, options), Err(err));
+        }
+    }
+}